@@ -0,0 +1,236 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use serde::{Deserialize, Serialize};
+use ringhopper::primitives::primitive::TagPath;
+use crate::tags::get_tag_file_path;
+use crate::error::Error;
+use crate::output::{progress, EventSink};
+
+const BACKUP_RECORD_FILE: &str = "backup.json";
+//the copied files live under this directory, mirroring their path in the tags directory
+const BACKUP_FILES_DIR: &str = "files";
+
+/// Copies of every file a run overwrote or deleted, so the run can be rolled back.
+pub struct Backup {
+    tags_dir: PathBuf,
+    dir: PathBuf,
+    record: BackupRecord,
+}
+
+/// The files a backup covers.
+#[derive(Serialize, Deserialize, Default)]
+pub struct BackupRecord {
+    /// The tags directory the files are in.
+    pub tags_dir: String,
+    /// Files which existed before the run, relative to the tags directory.
+    pub replaced: Vec<String>,
+    /// Files which the run created, so restoring deletes them.
+    pub created: Vec<String>,
+}
+
+/// A backup on disk.
+pub struct BackupInfo {
+    /// When the backup was made, which also identifies it.
+    pub timestamp: String,
+    /// The files it covers.
+    pub record: BackupRecord,
+}
+
+impl Backup {
+    /// Starts a backup of files in the tags directory. Nothing is created on disk until the first file is saved.
+    pub fn new(tags_dir: &Path) -> Backup {
+        let backups_dir = get_backups_dir(tags_dir);
+        let timestamp = chrono::Local::now().format("%Y%m%d-%H%M%S").to_string();
+        let mut dir = backups_dir.join(&timestamp);
+        let mut suffix = 1;
+        while dir.exists() {
+            dir = backups_dir.join(format!("{}-{}", timestamp, suffix));
+            suffix += 1;
+        }
+        Backup {
+            tags_dir: tags_dir.to_owned(),
+            dir,
+            record: BackupRecord::default(),
+        }
+    }
+
+    pub(crate) fn save_tag(&mut self, tag_path: &TagPath, sink: &dyn EventSink) -> Result<(), Error> {
+        self.save_relative(&get_tag_file_path(tag_path), sink)
+    }
+
+    pub(crate) fn save_file(&mut self, path: &Path, sink: &dyn EventSink) -> Result<(), Error> {
+        let relative_path = path.strip_prefix(&self.tags_dir).map_err(|_| Error::Io { message: format!("{} is outside of the tags directory", path.display()), source: None })?;
+        self.save_relative(relative_path, sink)
+    }
+
+    //only the first save of a file counts, since that's its state from before the run
+    fn save_relative(&mut self, relative_path: &Path, sink: &dyn EventSink) -> Result<(), Error> {
+        let relative = relative_path.to_string_lossy().into_owned();
+        if self.record.replaced.contains(&relative) || self.record.created.contains(&relative) {
+            return Ok(());
+        }
+
+        let source_path = self.tags_dir.join(relative_path);
+        if source_path.exists() {
+            let backup_path = self.dir.join(BACKUP_FILES_DIR).join(relative_path);
+            if let Some(parent) = backup_path.parent() {
+                fs::create_dir_all(parent).map_err(|err| Error::io(format!("Could not create backup directory {}: {}", parent.display(), err), err))?;
+            }
+            fs::copy(&source_path, &backup_path).map_err(|err| Error::io(format!("Could not back up {}: {}", source_path.display(), err), err))?;
+            progress!(sink, "Backed up {} to {}", source_path.display(), backup_path.display());
+            self.record.replaced.push(relative);
+        } else {
+            self.record.created.push(relative);
+        }
+
+        //the record is rewritten every time so a run which fails partway can still be restored
+        if self.record.tags_dir.is_empty() {
+            let tags_dir = fs::canonicalize(&self.tags_dir).map_err(|err| Error::io(format!("Could not resolve tags directory {}: {}", self.tags_dir.display(), err), err))?;
+            self.record.tags_dir = tags_dir.to_string_lossy().into_owned();
+        }
+        write_backup_record(&self.dir, &self.record)
+    }
+}
+
+//backups are kept next to the tags directory rather than in it, so they never get built into maps
+fn get_backups_dir(tags_dir: &Path) -> PathBuf {
+    let parent = tags_dir.parent().unwrap_or(Path::new(""));
+    parent.join(".spawn-camp").join("backups")
+}
+
+/// Every backup of the tags directory, oldest first.
+pub fn list_backups(tags_dir: &Path) -> Result<Vec<BackupInfo>, Error> {
+    let backups_dir = get_backups_dir(tags_dir);
+    if !backups_dir.exists() {
+        return Ok(Vec::new());
+    }
+    let entries = fs::read_dir(&backups_dir).map_err(|err| Error::io(format!("Could not read backups directory {}: {}", backups_dir.display(), err), err))?;
+
+    let mut backups: Vec<BackupInfo> = Vec::new();
+    for entry in entries {
+        let entry = entry.map_err(|err| Error::io(format!("Could not read backups directory {}: {}", backups_dir.display(), err), err))?;
+        let record_path = entry.path().join(BACKUP_RECORD_FILE);
+        if !record_path.exists() {
+            continue;
+        }
+        backups.push(BackupInfo {
+            timestamp: entry.file_name().to_string_lossy().into_owned(),
+            record: read_backup_record(&record_path)?,
+        });
+    }
+    backups.sort_by(|a, b| a.timestamp.cmp(&b.timestamp));
+    Ok(backups)
+}
+
+/// Restores the given backup of the tags directory, or the most recent one. Returns the timestamp restored.
+pub fn restore_backup(tags_dir: &Path, timestamp: Option<&str>, sink: &dyn EventSink) -> Result<String, Error> {
+    let backups = list_backups(tags_dir)?;
+    let backup = match timestamp {
+        Some(timestamp) => backups.iter().find(|backup| backup.timestamp == timestamp).ok_or(Error::Options(format!("No backup exists with timestamp {}", timestamp)))?,
+        None => backups.last().ok_or(Error::Io { message: format!("No backups exist in {}", get_backups_dir(tags_dir).display()), source: None })?,
+    };
+    let backup_files_dir = get_backups_dir(tags_dir).join(&backup.timestamp).join(BACKUP_FILES_DIR);
+    let restore_tags_dir = Path::new(&backup.record.tags_dir);
+
+    progress!(sink, "Restoring backup {} into {}", backup.timestamp, restore_tags_dir.display());
+    for relative in &backup.record.replaced {
+        let backup_path = backup_files_dir.join(relative);
+        let target_path = restore_tags_dir.join(relative);
+        progress!(sink, "Restoring {}", target_path.display());
+        if let Some(parent) = target_path.parent() {
+            fs::create_dir_all(parent).map_err(|err| Error::io(format!("Could not create directory {}: {}", parent.display(), err), err))?;
+        }
+        fs::copy(&backup_path, &target_path).map_err(|err| Error::io(format!("Could not restore {}: {}", target_path.display(), err), err))?;
+    }
+    for relative in &backup.record.created {
+        let target_path = restore_tags_dir.join(relative);
+        if target_path.exists() {
+            progress!(sink, "Deleting {}", target_path.display());
+            fs::remove_file(&target_path).map_err(|err| Error::io(format!("Could not delete {}: {}", target_path.display(), err), err))?;
+        }
+    }
+
+    Ok(backup.timestamp.clone())
+}
+
+/// Deletes all but the most recent backups of the tags directory. Returns how many were deleted.
+pub fn prune_backups(tags_dir: &Path, keep: usize, sink: &dyn EventSink) -> Result<usize, Error> {
+    let backups = list_backups(tags_dir)?;
+    let prune_count = backups.len().saturating_sub(keep);
+    for backup in &backups[..prune_count] {
+        let backup_dir = get_backups_dir(tags_dir).join(&backup.timestamp);
+        progress!(sink, "Deleting backup {}", backup.timestamp);
+        fs::remove_dir_all(&backup_dir).map_err(|err| Error::io(format!("Could not delete backup {}: {}", backup_dir.display(), err), err))?;
+    }
+    Ok(prune_count)
+}
+
+fn read_backup_record(path: &Path) -> Result<BackupRecord, Error> {
+    let contents = fs::read_to_string(path).map_err(|err| Error::io(format!("Could not read backup record {}: {}", path.display(), err), err))?;
+    serde_json::from_str(&contents).map_err(|err| Error::io(format!("Could not parse backup record {}: {}", path.display(), err), err))
+}
+
+fn write_backup_record(dir: &Path, record: &BackupRecord) -> Result<(), Error> {
+    fs::create_dir_all(dir).map_err(|err| Error::io(format!("Could not create backup directory {}: {}", dir.display(), err), err))?;
+    let path = dir.join(BACKUP_RECORD_FILE);
+    let contents = serde_json::to_string_pretty(record).map_err(|err| Error::io(format!("Could not serialize backup record: {}", err), err))?;
+    fs::write(&path, contents).map_err(|err| Error::io(format!("Could not write backup record {}: {}", path.display(), err), err))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::output::Discard;
+    use std::process;
+
+    //a tags directory inside its own directory, so its backups don't mix with other tests'
+    fn get_test_tags_dir(name: &str) -> PathBuf {
+        let tags_dir = std::env::temp_dir().join(format!("spawn-camp-{}-{}", name, process::id())).join("tags");
+        fs::create_dir_all(&tags_dir).unwrap();
+        tags_dir
+    }
+
+    #[test]
+    fn restore_undoes_replaced_and_created_files() {
+        let tags_dir = get_test_tags_dir("backup-restore");
+        let replaced = tags_dir.join("replaced.scenario");
+        let created = tags_dir.join("created.bitmap");
+        fs::write(&replaced, "before").unwrap();
+
+        let mut backup = Backup::new(&tags_dir);
+        backup.save_file(&replaced, &Discard).unwrap();
+        fs::write(&replaced, "after").unwrap();
+        //only the first save counts, so the backup keeps the state from before the run
+        backup.save_file(&replaced, &Discard).unwrap();
+        backup.save_file(&created, &Discard).unwrap();
+        fs::write(&created, "new").unwrap();
+
+        let backups = list_backups(&tags_dir).unwrap();
+        assert_eq!(backups.len(), 1);
+        assert_eq!(backups[0].record.replaced, vec!["replaced.scenario"]);
+        assert_eq!(backups[0].record.created, vec!["created.bitmap"]);
+
+        assert_eq!(restore_backup(&tags_dir, None, &Discard).unwrap(), backups[0].timestamp);
+        assert_eq!(fs::read_to_string(&replaced).unwrap(), "before");
+        assert!(!created.exists());
+        fs::remove_dir_all(tags_dir.parent().unwrap()).unwrap();
+    }
+
+    #[test]
+    fn prune_keeps_the_most_recent() {
+        let tags_dir = get_test_tags_dir("backup-prune");
+        let file = tags_dir.join("test.scenario");
+        fs::write(&file, "").unwrap();
+        //backups made within the same second get numbered suffixes, which still sort in order
+        let timestamps: Vec<String> = (0..3).map(|_| {
+            Backup::new(&tags_dir).save_file(&file, &Discard).unwrap();
+            list_backups(&tags_dir).unwrap().last().unwrap().timestamp.clone()
+        }).collect();
+
+        assert_eq!(prune_backups(&tags_dir, 1, &Discard).unwrap(), 2);
+        let remaining: Vec<String> = list_backups(&tags_dir).unwrap().into_iter().map(|backup| backup.timestamp).collect();
+        assert_eq!(remaining, vec![timestamps[2].clone()]);
+        assert_eq!(prune_backups(&tags_dir, 1, &Discard).unwrap(), 0);
+        fs::remove_dir_all(tags_dir.parent().unwrap()).unwrap();
+    }
+}
@@ -0,0 +1,336 @@
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+use clap::ArgMatches;
+use ringhopper::primitives::primitive::{TagGroup, TagPath};
+use spawn_camp::backup::Backup;
+use spawn_camp::layers::{find_tags, TagsLayers};
+use spawn_camp::lm_render::LmRenderer;
+use spawn_camp::manifest::get_manifest_path;
+use spawn_camp::output::{progress, ErrorInfo, Event, EventSink, Printer};
+use spawn_camp::plan::{write_plan_json, ChangePlan};
+use spawn_camp::run::{self, MigrateOptions, SpawnOptions};
+use spawn_camp::tags::{open_tags_directory, parse_tag_path, LayeredTags};
+use spawn_camp::error::Error;
+use crate::config::{Config, ResolvedArgs};
+use crate::options::{get_patterns, parse_spawn_options};
+use crate::watch::{accept_writes, run_watch, take_snapshots};
+
+pub struct ScenarioResult {
+    pub scenario: String,
+    pub result: Result<String, Error>,
+    pub duration: Duration,
+    //tag files the run wrote, so watching can tell them apart from the user's own saves
+    pub written_files: Vec<PathBuf>,
+}
+
+#[derive(Copy, Clone, PartialEq)]
+enum Operation {
+    Spawns,
+    Reset,
+    Validate,
+    Migrate,
+}
+
+pub struct RunOptions {
+    operation: Operation,
+    keep_bitmap: bool,
+    pub dry_run: bool,
+    //only for the migrate command
+    migrate: Option<MigrateOptions>,
+    spawn: Vec<(TagPath, SpawnOptions)>,
+}
+
+impl RunOptions {
+    fn get_spawn_options(&self, scenario_tag_path: &TagPath) -> &SpawnOptions {
+        self.spawn.iter()
+            .find(|(tag_path, _)| tag_path == scenario_tag_path)
+            .map(|(_, options)| options)
+            .expect("options are resolved for every scenario")
+    }
+}
+
+//kept between scenarios, and between re-runs when watching
+pub struct RunState {
+    //created on first use since resets, validation and marker placement don't render
+    renderer: Option<LmRenderer>,
+    //one backup covers every scenario and every re-run while watching, so restoring it undoes the whole invocation
+    backup: Option<Backup>,
+    plans: Vec<ChangePlan>,
+}
+
+//the commands which run on scenarios, each in turn. with --watch they're run again whenever they change
+pub fn run_batch(command: &str, matches: &ArgMatches, config: Option<&Config>, layers: TagsLayers, printer: &Printer) -> Result<String, Error> {
+    let operation = match command {
+        "apply" | "render" | "markers" => Operation::Spawns,
+        "reset" => Operation::Reset,
+        "validate" => Operation::Validate,
+        "migrate" => Operation::Migrate,
+        _ => unreachable!("{} doesn't run on scenarios", command),
+    };
+    //only options the command defines can be read, so flags of other commands are treated as off
+    let has_flag = |id: &str| matches!(matches.try_get_one::<bool>(id), Ok(Some(true)));
+    let keep_bitmap = has_flag("keep-bitmap");
+    let migrate = match command {
+        "migrate" => Some(MigrateOptions { patterns: get_patterns(matches), remove: has_flag("remove") }),
+        _ => None,
+    };
+    let dry_run = has_flag("dry-run");
+    let watch = has_flag("watch");
+    let plan_json_path = matches.try_get_one::<String>("plan-json").ok().flatten().map(Path::new);
+
+    let mut tags = open_tags_directory(&layers)?;
+    let raw_scenario_tag_paths: Vec<&str> = matches.get_many::<String>("scenario-tag-path").unwrap().map(String::as_str).collect();
+    let scenario_tag_paths = expand_scenario_tag_paths(&raw_scenario_tag_paths, &layers)?;
+    let batch = scenario_tag_paths.len() > 1;
+    if operation != Operation::Validate {
+        if let Some(config) = config {
+            progress!(printer, "Using options from {}", config.path.display());
+        }
+        layers.print(printer);
+    }
+
+    let spawn = scenario_tag_paths.iter()
+        .map(|scenario_tag_path| {
+            let args = ResolvedArgs::new(matches, config, Some(scenario_tag_path));
+            parse_spawn_options(command, &args).map(|options| (scenario_tag_path.clone(), options))
+        })
+        .collect::<Result<_, Error>>()?;
+    let run_options = RunOptions {
+        operation,
+        keep_bitmap,
+        dry_run,
+        migrate,
+        spawn,
+    };
+    let mut state = RunState {
+        renderer: None,
+        backup: if dry_run { None } else { Some(Backup::new(&layers.write)) },
+        plans: Vec::new(),
+    };
+    let mut watched = if watch { take_snapshots(&tags, &layers, &scenario_tag_paths, printer) } else { Vec::new() };
+    let results = run_scenarios(&mut tags, &layers, &scenario_tag_paths, &run_options, &mut state, printer);
+    accept_writes(&mut watched, &results);
+
+    if let Some(plan_json_path) = plan_json_path {
+        write_plan_json(plan_json_path, &state.plans, printer)?;
+    }
+    if watch {
+        if batch {
+            print_summary(&results, printer);
+        }
+        return run_watch(&mut tags, &layers, watched, &run_options, &mut state, printer);
+    }
+    if !batch {
+        return results.into_iter().next().unwrap().result;
+    }
+
+    print_summary(&results, printer);
+    let failed = results.iter().filter(|result| result.result.is_err()).count();
+    if failed > 0 {
+        Err(Error::ScenariosFailed { failed, total: results.len() })
+    } else {
+        Ok(format!("{} scenarios processed successfully", results.len()))
+    }
+}
+
+//runs each scenario in turn, carrying on past any which fail
+pub fn run_scenarios(tags: &mut LayeredTags, layers: &TagsLayers, scenario_tag_paths: &[TagPath], options: &RunOptions, state: &mut RunState, printer: &Printer) -> Vec<ScenarioResult> {
+    let batch = scenario_tag_paths.len() > 1;
+    let mut results: Vec<ScenarioResult> = Vec::new();
+
+    for scenario_tag_path in scenario_tag_paths {
+        let scenario = scenario_tag_path.to_string();
+        if batch || printer.json {
+            printer.emit(Event::ScenarioStarted { scenario: &scenario });
+        }
+        let start = Instant::now();
+        let manifest_path = get_manifest_path(&layers.write, scenario_tag_path);
+        let mut plan = ChangePlan::new(options.dry_run, layers.clone());
+        plan.scenario = Some(scenario_tag_path.to_string());
+        plan.backup = state.backup.take();
+
+        let spawn = options.get_spawn_options(scenario_tag_path);
+        let result = match options.operation {
+            Operation::Validate => run::validate(tags, scenario_tag_path, &manifest_path, printer),
+            Operation::Reset => {
+                let markers = &spawn.markers.as_ref().expect("reset always has markers").markers;
+                run::reset(tags, scenario_tag_path, &manifest_path, markers, options.keep_bitmap, &mut plan, printer)
+            },
+            Operation::Migrate => {
+                let migrate = options.migrate.as_ref().expect("migrate always has migrate options");
+                run::migrate(tags, scenario_tag_path, &manifest_path, migrate, &mut plan, printer)
+            },
+            Operation::Spawns => get_renderer(&mut state.renderer, spawn)
+                .and_then(|renderer| run::apply(tags, renderer, scenario_tag_path, &manifest_path, spawn, &mut plan, printer)),
+        };
+        state.backup = plan.backup.take();
+        let written_files = match options.dry_run {
+            true => Vec::new(),
+            false => plan.tags.iter().map(|tag| layers.write.join(tag.split('\\').collect::<PathBuf>())).collect(),
+        };
+
+        let result = match result {
+            Ok(_) if options.dry_run => {
+                printer.emit(Event::Plan { plan: &plan });
+                state.plans.push(plan);
+                Ok("Dry run complete, no tags were written".to_owned())
+            },
+            result => result,
+        };
+        let duration = start.elapsed();
+        if batch || printer.json {
+            emit_scenario_finished(&scenario, &result, duration, printer);
+        }
+        results.push(ScenarioResult {
+            scenario,
+            result,
+            duration,
+            written_files,
+        });
+    }
+
+    results
+}
+
+//the renderer is created on first use, so a scenario which fails to create it doesn't stop the rest trying
+fn get_renderer<'a>(renderer: &'a mut Option<LmRenderer>, options: &SpawnOptions) -> Result<Option<&'a LmRenderer>, Error> {
+    if options.render.is_none() {
+        return Ok(None);
+    }
+    if renderer.is_none() {
+        *renderer = Some(LmRenderer::init()?);
+    }
+    Ok(renderer.as_ref())
+}
+
+pub fn emit_scenario_finished(scenario: &str, result: &Result<String, Error>, duration: Duration, printer: &Printer) {
+    printer.emit(Event::ScenarioFinished {
+        scenario,
+        ok: result.is_ok(),
+        message: result.as_ref().ok().map(String::as_str),
+        error: result.as_ref().err().map(ErrorInfo::new),
+        duration_seconds: duration.as_secs_f64(),
+    });
+}
+
+//scenario arguments may be tag paths or patterns like levels\competitive\*, matched against every scenario in the tags directories
+fn expand_scenario_tag_paths(raws: &[&str], layers: &TagsLayers) -> Result<Vec<TagPath>, Error> {
+    let mut all_scenarios: Option<Vec<TagPath>> = None;
+    let mut scenario_tag_paths: Vec<TagPath> = Vec::new();
+
+    for raw in raws {
+        let matched = if is_pattern(raw) {
+            if all_scenarios.is_none() {
+                all_scenarios = Some(find_tags(&layers.sources(), TagGroup::Scenario)?);
+            }
+            let mut matched: Vec<TagPath> = all_scenarios.iter().flatten()
+                .filter(|tag_path| matches_pattern(raw, tag_path.path()))
+                .cloned()
+                .collect();
+            if matched.is_empty() {
+                return Err(Error::Options(format!("No scenarios match {}", raw)));
+            }
+            matched.sort_by(|a, b| a.path().cmp(b.path()));
+            matched
+        } else {
+            vec![parse_tag_path(raw, TagGroup::Scenario)?]
+        };
+        matched.into_iter().for_each(|tag_path| {
+            if !scenario_tag_paths.contains(&tag_path) {
+                scenario_tag_paths.push(tag_path);
+            }
+        });
+    }
+
+    Ok(scenario_tag_paths)
+}
+
+pub fn print_summary(results: &[ScenarioResult], printer: &Printer) {
+    //each scenario already had a scenario_finished event
+    if printer.json {
+        return;
+    }
+    let width = results.iter().map(|result| result.scenario.len()).max().unwrap_or(0).max("Scenario".len());
+    println!();
+    println!("{:<width$}  {:<6}  {:>8}", "Scenario", "Result", "Time", width = width);
+    results.iter().for_each(|result| {
+        let (status, error) = match &result.result {
+            Ok(_) => ("ok", String::new()),
+            //only the first line, since validation errors list every problem
+            Err(err) => ("FAILED", format!("  {}", err.to_string().lines().next().unwrap_or_default())),
+        };
+        println!("{:<width$}  {:<6}  {:>7.1}s{}", result.scenario, status, result.duration.as_secs_f64(), error, width = width);
+    });
+
+    let failed = results.iter().filter(|result| result.result.is_err()).count();
+    let total: Duration = results.iter().map(|result| result.duration).sum();
+    println!("{} succeeded, {} failed in {:.1}s", results.len() - failed, failed, total.as_secs_f64());
+}
+
+fn is_pattern(raw: &str) -> bool {
+    raw.contains('*') || raw.contains('?')
+}
+
+//* matches any characters including path separators, so levels\competitive\* matches every scenario in that folder.
+//? matches a single character. matching is case insensitive like tag paths, and / matches \ so patterns can be typed
+//either way
+fn matches_pattern(pattern: &str, path: &str) -> bool {
+    let normalize = |raw: &str| -> Vec<char> { raw.to_ascii_lowercase().chars().map(|c| if c == '/' { '\\' } else { c }).collect() };
+    let pattern = normalize(pattern);
+    let path = normalize(path);
+
+    let (mut p, mut t) = (0, 0);
+    //where to resume from if the characters after the last * stop matching
+    let mut backtrack: Option<(usize, usize)> = None;
+    while t < path.len() {
+        if p < pattern.len() && (pattern[p] == '?' || pattern[p] == path[t]) {
+            p += 1;
+            t += 1;
+        } else if p < pattern.len() && pattern[p] == '*' {
+            backtrack = Some((p, t));
+            p += 1;
+        } else if let Some((star_p, star_t)) = backtrack {
+            p = star_p + 1;
+            t = star_t + 1;
+            backtrack = Some((star_p, star_t + 1));
+        } else {
+            return false;
+        }
+    }
+    pattern[p..].iter().all(|c| *c == '*')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn star_matches_any_characters_including_separators() {
+        assert!(matches_pattern("levels\\competitive\\*", "levels\\competitive\\ruins\\ruins"));
+        assert!(matches_pattern("levels\\*\\ruins", "levels\\competitive\\ruins\\ruins"));
+        assert!(matches_pattern("*", "levels\\test\\test"));
+        assert!(matches_pattern("levels\\*ruins*", "levels\\competitive\\ruins\\ruins"));
+        assert!(!matches_pattern("levels\\competitive\\*", "levels\\test\\ruins\\ruins"));
+        assert!(!matches_pattern("*\\ruins", "levels\\competitive\\ruins\\ruins_night"));
+    }
+
+    #[test]
+    fn question_mark_matches_one_character() {
+        assert!(matches_pattern("levels\\test\\map?\\map?", "levels\\test\\map1\\map1"));
+        assert!(!matches_pattern("levels\\test\\map?", "levels\\test\\map"));
+        assert!(!matches_pattern("levels\\test\\map?", "levels\\test\\map10"));
+    }
+
+    #[test]
+    fn matching_ignores_case() {
+        assert!(matches_pattern("Levels\\Competitive\\*", "levels\\competitive\\ruins\\ruins"));
+        assert!(matches_pattern("levels\\competitive\\*", "LEVELS\\Competitive\\Ruins\\Ruins"));
+    }
+
+    #[test]
+    fn slashes_match_backslashes() {
+        assert!(matches_pattern("levels/competitive/*", "levels\\competitive\\ruins\\ruins"));
+        assert!(matches_pattern("levels/test/map?/map?", "levels\\test\\map1\\map1"));
+        assert!(!matches_pattern("levels/competitive/*", "levels\\test\\ruins\\ruins"));
+    }
+}
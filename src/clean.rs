@@ -0,0 +1,73 @@
+use ringhopper::definitions::ScenarioStructureBSP;
+use ringhopper::primitives::primitive::{TagGroup, TagPath};
+use ringhopper::tag::tree::VirtualTagsDirectory;
+use crate::layers::{find_tags, TagsLayers};
+use crate::manifest::find_manifests;
+use crate::randoms::get_output_lm_tag_path;
+use crate::tags::{open_tag, parse_tag_path, tag_group_err};
+use crate::error::Error;
+use crate::output::EventSink;
+
+//randoms bitmaps left behind by resets from older versions, or by BSPs which were since relit. only bitmaps spawn-camp
+//would have written are candidates: the `_randoms` bitmap of a BSP in any layer, or one recorded in a manifest. a
+//mapper's own bitmap which happens to end in `_randoms` is never touched. only bitmaps in the written layer are
+//returned, since the rest are read-only, but BSPs in every layer are checked
+pub fn find_unreferenced_randoms(tags: &VirtualTagsDirectory, layers: &TagsLayers, sink: &dyn EventSink) -> Result<Vec<TagPath>, Error> {
+    let mut referenced: Vec<TagPath> = Vec::new();
+    let mut candidates: Vec<TagPath> = Vec::new();
+    for bsp_tag_path in find_tags(&layers.all(), TagGroup::ScenarioStructureBSP)? {
+        //a BSP which can't be read might reference any of them, so nothing is reported as unreferenced
+        let bsp_tag = open_tag(tags, &bsp_tag_path, sink)?;
+        let bsp = bsp_tag.get_ref::<ScenarioStructureBSP>().ok_or_else(|| tag_group_err(&bsp_tag_path))?;
+        if let Some(lm_tag_path) = bsp.lightmaps_bitmap.path() {
+            referenced.push(lm_tag_path.clone());
+        }
+        candidates.push(get_output_lm_tag_path(&bsp_tag_path));
+    }
+    for manifest in find_manifests(&layers.write)? {
+        if let Some(lightmap) = manifest.lightmap {
+            candidates.push(parse_tag_path(&lightmap.randoms, TagGroup::Bitmap)?);
+        }
+    }
+
+    let is_in = |tag_paths: &[TagPath], tag_path: &TagPath| tag_paths.iter().any(|other| other.path().eq_ignore_ascii_case(tag_path.path()));
+    Ok(find_tags(&[layers.write.as_path()], TagGroup::Bitmap)?.into_iter()
+        .filter(|tag_path| is_in(&candidates, tag_path) && !is_in(&referenced, tag_path))
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::output::Discard;
+    use std::{fs, process};
+    use ringhopper::definitions::Bitmap;
+    use ringhopper::primitives::primitive::TagReference;
+    use ringhopper::tag::tree::TagTree;
+
+    fn tag_path(path: &str, group: TagGroup) -> TagPath {
+        TagPath::new(path, group).unwrap()
+    }
+
+    #[test]
+    fn only_unreferenced_bitmaps_spawn_camp_would_write_are_found() {
+        let dir = std::env::temp_dir().join(format!("spawn-camp-clean-{}", process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let mut tags = VirtualTagsDirectory::new(&[&dir], Some(0)).unwrap();
+
+        let referencing_bsp = ScenarioStructureBSP {
+            lightmaps_bitmap: TagReference::Set(tag_path("levels\\b\\b_randoms", TagGroup::Bitmap)),
+            ..Default::default()
+        };
+        tags.write_tag(&tag_path("levels\\a\\a", TagGroup::ScenarioStructureBSP), &ScenarioStructureBSP::default()).unwrap();
+        tags.write_tag(&tag_path("levels\\b\\b", TagGroup::ScenarioStructureBSP), &referencing_bsp).unwrap();
+        for bitmap in ["levels\\a\\a_randoms", "levels\\b\\b_randoms", "levels\\a\\bitmaps\\grass_randoms"] {
+            tags.write_tag(&tag_path(bitmap, TagGroup::Bitmap), &Bitmap::default()).unwrap();
+        }
+
+        let layers = TagsLayers::new(&dir, &[]).unwrap();
+        let unreferenced = find_unreferenced_randoms(&tags, &layers, &Discard).unwrap();
+        assert_eq!(unreferenced, vec![tag_path("levels\\a\\a_randoms", TagGroup::Bitmap)]);
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,320 @@
+use clap::{Arg, ArgAction, Command};
+use clap::builder::{Styles};
+use clap::{builder::styling};
+
+pub const SCALES: [&str; 5] = ["1", "2", "4", "8", "16"];
+
+//every command and its options. options which spawn-camp.toml can set are listed in config.rs too
+pub fn build_command() -> Command {
+    Command::new("spawn-camp")
+        .about("Add spawn markers and randoms information to Halo CE multiplayer levels.")
+        .version("0.1.0")
+        .styles(Styles::styled()
+            .header(styling::AnsiColor::Green.on_default() | styling::Effects::BOLD)
+            .usage(styling::AnsiColor::Green.on_default() | styling::Effects::BOLD)
+            .literal(styling::AnsiColor::Blue.on_default() | styling::Effects::BOLD)
+            .placeholder(styling::AnsiColor::Cyan.on_default())
+        )
+        .subcommand_required(true)
+        .subcommand(Command::new("apply")
+            .about("Renders randoms into the BSP's lightmap and places spawn markers.")
+            .args(scenarios_args())
+            .args(write_args())
+            .arg(watch_arg())
+            .args(marker_args())
+            .args(render_args())
+        )
+        .subcommand(Command::new("render")
+            .about("Renders randoms into the BSP's lightmap, leaving spawn markers as they are.")
+            .args(scenarios_args())
+            .args(write_args())
+            .arg(watch_arg())
+            .args(render_args())
+        )
+        .subcommand(Command::new("markers")
+            .about("Places spawn markers, leaving the BSP's lightmap as it is.")
+            .args(scenarios_args())
+            .args(write_args())
+            .arg(watch_arg())
+            .args(marker_args())
+        )
+        .subcommand(Command::new("reset")
+            .about("Removes spawn markers from the scenario and scenery palette, and resets the BSP's lightmap reference to its original bitmap.")
+            .args(scenarios_args())
+            .args(write_args())
+            .arg(Arg::new("keep-bitmap")
+                .long("keep-bitmap")
+                .help("If provided, the generated randoms lightmap bitmap is kept instead of being deleted.")
+                .action(ArgAction::SetTrue)
+            )
+            .args(marker_set_args())
+        )
+        .subcommand(Command::new("validate")
+            .about("Checks the BSP's original lightmap bitmap for problems which would prevent rendering randoms, without writing any tags.")
+            .args(scenarios_args())
+            .arg(output_tags_arg())
+        )
+        .subcommand(Command::new("export")
+            .about("Writes the scenario's spawns as JSON, for use in other tools.")
+            .arg(Arg::new("scenario-tag-path")
+                .value_name("scenario-tag-path")
+                .required(true)
+                .help("Tag path to your scenario, for example: levels\\test\\chillout\\chillout.")
+            )
+            .arg(Arg::new("file")
+                .value_name("path")
+                .long("file")
+                .short('f')
+                .help("File to write the spawns to. Defaults to printing them.")
+            )
+            .arg(output_tags_arg())
+        )
+        .subcommand(Command::new("migrate")
+            .about("Lists spawn markers and randoms injected by h1-spawn-tools, and removes them with --remove.")
+            .args(scenarios_args())
+            .args(write_args())
+            .arg(Arg::new("remove")
+                .long("remove")
+                .help("If provided, the injected scenery placements and scenery palette entries which were found are removed from the scenario. Randoms geometry compiled into the BSP is only listed.")
+                .action(ArgAction::SetTrue)
+            )
+            .arg(pattern_arg())
+        )
+        .subcommand(Command::new("report")
+            .about("Prints statistics about the scenario's spawn layout: counts by type and team, distances between slayer spawns, and slayer spawns by BSP cluster and height.")
+            .arg(Arg::new("scenario-tag-path")
+                .value_name("scenario-tag-path")
+                .required(true)
+                .help("Tag path to your scenario, for example: levels\\test\\chillout\\chillout.")
+            )
+            .arg(output_tags_arg())
+        )
+        .subcommand(Command::new("restore")
+            .about("Restores the tags backed up before a run, undoing it.")
+            .arg(Arg::new("timestamp")
+                .value_name("timestamp")
+                .help("Backup to restore, as shown by backups list. Defaults to the most recent.")
+            )
+            .arg(output_tags_arg())
+        )
+        .subcommand(Command::new("clean")
+            .about("Deletes randoms lightmap bitmaps which no BSP in the tags directory references.")
+            .arg(Arg::new("dry-run")
+                .long("dry-run")
+                .help("If provided, lists the bitmaps which would be deleted without deleting them.")
+                .action(ArgAction::SetTrue)
+            )
+            .arg(output_tags_arg())
+        )
+        .subcommand(Command::new("config")
+            .about("Shows the options read from spawn-camp.toml.")
+            .subcommand_required(true)
+            .subcommand(Command::new("show")
+                .about("Prints the effective value of each option and where it comes from. Options can be given to see how they combine with the config.")
+                .arg(Arg::new("scenario")
+                    .value_name("scenario-tag-path")
+                    .help("If provided, includes the overrides for this scenario.")
+                )
+                .arg(output_tags_arg())
+                .args(marker_args())
+                .args(render_args())
+            )
+        )
+        .subcommand(Command::new("backups")
+            .about("Manage the tag backups made before each run.")
+            .subcommand_required(true)
+            .arg(output_tags_arg())
+            .subcommand(Command::new("list")
+                .about("Lists backups and the files in each.")
+            )
+            .subcommand(Command::new("prune")
+                .about("Deletes all but the most recent backups.")
+                .arg(Arg::new("keep")
+                    .value_name("count")
+                    .long("keep")
+                    .help("Number of backups to keep.")
+                    .default_value("10")
+                )
+            )
+        )
+        .arg(Arg::new("tags")
+            .value_name("path")
+            .long("tags")
+            .short('t')
+            .help("Path to the base tags directory. Can be given multiple times to layer tags directories, highest priority first. Tags are only written to the first; the rest are read-only.")
+            .default_value("tags")
+            .action(ArgAction::Append)
+            .global(true)
+        )
+        .arg(Arg::new("output")
+            .value_name("format")
+            .long("output")
+            .help("Output format. json prints one JSON object per line for each event, ending with a result object, for use in build scripts.")
+            .default_value("text")
+            .value_parser(["text", "json"])
+            .global(true)
+        )
+}
+
+fn scenarios_args() -> [Arg; 1] {
+    [
+        Arg::new("scenario-tag-path")
+            .value_name("scenario-tag-path")
+            .required(true)
+            .num_args(1..)
+            .help("Tag path to your scenario, for example: levels\\test\\chillout\\chillout. Multiple scenarios and patterns like levels\\competitive\\* can be given to process them all, where * matches any characters."),
+    ]
+}
+
+//options for commands which write tags
+fn write_args() -> [Arg; 3] {
+    [
+        Arg::new("dry-run")
+            .long("dry-run")
+            .help("If provided, everything is read and rendered as usual, but instead of writing any tags the planned changes are printed.")
+            .action(ArgAction::SetTrue),
+        Arg::new("plan-json")
+            .value_name("path")
+            .long("plan-json")
+            .help("With --dry-run, also writes the planned changes to this file as JSON.")
+            .requires("dry-run"),
+        output_tags_arg(),
+    ]
+}
+
+pub fn output_tags_arg() -> Arg {
+    Arg::new("output-tags")
+        .value_name("path")
+        .long("output-tags")
+        .short('o')
+        .help("If provided, modified and generated tags are written to this directory instead, leaving every --tags directory untouched. The scenario and BSP are always read from the --tags directories, and building with this directory first gives the randoms build.")
+}
+
+fn pattern_arg() -> Arg {
+    Arg::new("pattern")
+        .value_name("text")
+        .long("pattern")
+        .action(ArgAction::Append)
+        .help("Also treats scenery and BSP shaders whose tag path contains this text as injected by h1-spawn-tools, for example randoms. Can be given multiple times. Only h1-spawn-tools' marker scenery is matched by default.")
+}
+
+fn watch_arg() -> Arg {
+    Arg::new("watch")
+        .long("watch")
+        .help("If provided, keeps running after the first run and runs again whenever the scenario or its original lightmap bitmap is saved.")
+        .action(ArgAction::SetTrue)
+}
+
+//which scenery counts as a marker, needed to place markers and to remove them from scenarios without a manifest
+fn marker_set_args() -> [Arg; 4] {
+    [
+        Arg::new("marker-tag-path")
+            .value_name("tag-path")
+            .long("marker")
+            .short('m')
+            .help("Tag path for the spawn marker scenery.")
+            .default_value("scenery\\spawn_marker_nhe\\spawn_marker_nhe"),
+        Arg::new("marker-type")
+            .value_name("type=tag-path")
+            .long("marker-type")
+            .help("Spawn marker scenery for spawns of the given type, for example ctf=scenery\\spawn_marker_ctf\\spawn_marker_ctf. Types are ctf, slayer, oddball, koth, race, all, noctf and noracectf. Spawns of a mapped type get markers even if they aren't slayer spawns. Can be given multiple times.")
+            .action(ArgAction::Append),
+        Arg::new("marker-red-tag-path")
+            .value_name("tag-path")
+            .long("marker-red")
+            .help("Tag path for the spawn marker scenery used by red team (team index 0) spawns. Defaults to the --marker scenery."),
+        Arg::new("marker-blue-tag-path")
+            .value_name("tag-path")
+            .long("marker-blue")
+            .help("Tag path for the spawn marker scenery used by blue team (team index 1) spawns. Defaults to the --marker scenery."),
+    ]
+}
+
+pub fn marker_args() -> Vec<Arg> {
+    let mut args = marker_set_args().to_vec();
+    args.extend([
+        Arg::new("create-marker")
+            .long("create-marker")
+            .help("If provided, any marker scenery which doesn't exist is generated as a simple arrow, along with its model and shader.")
+            .action(ArgAction::SetTrue),
+        no_flag_arg("create-marker", "no-create-marker"),
+        Arg::new("name-markers")
+            .long("name-markers")
+            .short('n')
+            .help("If provided, each spawn marker is given an object name like spawn_012_slayer_red so it can be identified in Sapien.")
+            .action(ArgAction::SetTrue),
+        no_flag_arg("name-markers", "no-name-markers"),
+        Arg::new("ground-snap")
+            .long("ground-snap")
+            .short('g')
+            .help("If provided, each spawn marker is moved down (or up) onto the BSP's collision geometry. The spawns themselves are not moved.")
+            .action(ArgAction::SetTrue),
+        no_flag_arg("ground-snap", "no-ground-snap"),
+        Arg::new("snap-tolerance")
+            .value_name("units")
+            .long("snap-tolerance")
+            .help("How far above and below each spawn to look for ground when snapping markers.")
+            .default_value("0.5"),
+        Arg::new("ground-report")
+            .value_name("units")
+            .long("ground-report")
+            .help("When snapping markers, spawns further than this from the ground are reported.")
+            .default_value("0.05"),
+    ]);
+    args
+}
+
+pub fn render_args() -> [Arg; 8] {
+    [
+        Arg::new("lm-scale")
+            .value_name("num")
+            .long("scale")
+            .short('s')
+            .help("Scale for the randoms lightmap compared to Tool's lightmap. Higher scale results in sharper randoms, but increases the tag size.")
+            .default_value("4")
+            .value_parser(SCALES),
+        Arg::new("max-size")
+            .value_name("MiB")
+            .long("max-size")
+            .help("If provided, the scale of the largest lightmap pages is reduced until the randoms lightmap tag fits within this size, including its metadata. The --scale option becomes the maximum scale per page."),
+        Arg::new("max-dimension")
+            .value_name("px")
+            .long("max-dimension")
+            .help("If provided, the scale of each lightmap page is reduced until its width and height fit within this many pixels."),
+        Arg::new("randoms-color")
+            .value_name("hex-code")
+            .long("color")
+            .short('c')
+            .help("Color to render randoms in the lightmap. Supports RGB(A) hex codes like: #FF00FF, #0FF, #DDA0DD80 (alpha controls opacity).")
+            .default_value("#FF000080"),
+        Arg::new("blend")
+            .value_name("mode")
+            .long("blend")
+            .short('b')
+            .help("Color blend mode for the randoms overlay over the original lightmap.")
+            .default_value("multiply")
+            .value_parser(["normal", "multiply"]),
+        Arg::new("dither")
+            .value_name("mode")
+            .long("dither")
+            .short('d')
+            .help("Dithering used when reducing the rendered randoms lightmap to 16-bit color. Ordered or diffusion dithering hides banding in soft blends.")
+            .default_value("none")
+            .value_parser(["none", "ordered", "diffusion"]),
+        Arg::new("walkable")
+            .long("walkable")
+            .short('w')
+            .help("If provided, only walkable surfaces up to 45 degrees steepness will be shaded with the randoms color.")
+            .action(ArgAction::SetTrue),
+        no_flag_arg("walkable", "no-walkable"),
+    ]
+}
+
+//turns off a flag which spawn-camp.toml turns on. whichever of the two is given last wins
+fn no_flag_arg(id: &'static str, no_id: &'static str) -> Arg {
+    Arg::new(no_id)
+        .long(no_id)
+        .help(format!("Turns off --{} for this run when spawn-camp.toml turns it on.", id))
+        .action(ArgAction::SetTrue)
+        .overrides_with(id)
+}
@@ -0,0 +1,97 @@
+use std::path::Path;
+use std::str::FromStr;
+use clap::ArgMatches;
+use ringhopper::definitions::Scenario;
+use ringhopper::primitives::primitive::TagGroup;
+use spawn_camp::backup::{list_backups, prune_backups, restore_backup};
+use spawn_camp::error::Error;
+use spawn_camp::export::{write_spawn_export, SpawnExport};
+use spawn_camp::layers::TagsLayers;
+use spawn_camp::output::{progress, Event, EventSink, Printer};
+use spawn_camp::run;
+use spawn_camp::spawns::get_spawns;
+use spawn_camp::tags::{open_tag, open_tags_directory, parse_tag_path, tag_group_err};
+use crate::config::{Config, ResolvedArgs, CONFIG_KEYS};
+use crate::options::parse_spawn_options;
+
+//without a scenario only the config's defaults are shown, since overrides apply to a single scenario
+pub fn run_config(config: Option<&Config>, matches: &ArgMatches, printer: &Printer) -> Result<String, Error> {
+    let Some(("show", matches)) = matches.subcommand() else {
+        unreachable!("config requires a subcommand")
+    };
+    let scenario_tag_path = matches.get_one::<String>("scenario").map(|raw| parse_tag_path(raw, TagGroup::Scenario)).transpose()?;
+    let args = ResolvedArgs::new(matches, config, scenario_tag_path.as_ref());
+
+    match config {
+        Some(config) => progress!(printer, "Config file: {}", config.path.display()),
+        None => progress!(printer, "No spawn-camp.toml found in the working directory or its parents"),
+    }
+    if let Some(scenario_tag_path) = &scenario_tag_path {
+        progress!(printer, "Scenario: {}", scenario_tag_path);
+    }
+    let width = CONFIG_KEYS.iter().map(|(_, key)| key.len()).max().unwrap_or(0);
+    for (id, key) in CONFIG_KEYS {
+        //flags are the only options clap stores as bools
+        let value = if matches!(matches.try_get_one::<bool>(id), Ok(Some(_))) {
+            args.get_flag(id)?.to_string()
+        } else {
+            let values = args.get_many(id)?;
+            if values.is_empty() { "(not set)".to_owned() } else { values.join(", ") }
+        };
+        progress!(printer, "{:<width$} = {}  ({})", key, value, args.get_source(id).describe(), width = width);
+    }
+
+    //options are parsed as they would be for a run, so mistakes in the config are reported here too
+    parse_spawn_options("apply", &args)?;
+    Ok("Config is valid".to_owned())
+}
+
+pub fn run_export(layers: TagsLayers, matches: &ArgMatches, printer: &Printer) -> Result<String, Error> {
+    let tags = open_tags_directory(&layers)?;
+    let scenario_tag_path = parse_tag_path(matches.get_one::<String>("scenario-tag-path").unwrap(), TagGroup::Scenario)?;
+    let scenario_tag = open_tag(tags.sources(), &scenario_tag_path, printer)?;
+    let scenario = scenario_tag.get_ref::<Scenario>().ok_or_else(|| tag_group_err(&scenario_tag_path))?;
+
+    let spawns = get_spawns(scenario);
+    let path = matches.get_one::<String>("file").map(Path::new);
+    write_spawn_export(&SpawnExport::new(scenario_tag_path.path(), &spawns), path, printer)?;
+    match path {
+        Some(path) => Ok(format!("{} spawn(s) exported to {}", spawns.len(), path.display())),
+        //nothing else is printed so the output stays valid JSON
+        None => Ok(String::new()),
+    }
+}
+
+pub fn run_report(layers: TagsLayers, matches: &ArgMatches, printer: &Printer) -> Result<String, Error> {
+    let tags = open_tags_directory(&layers)?;
+    let scenario_tag_path = parse_tag_path(matches.get_one::<String>("scenario-tag-path").unwrap(), TagGroup::Scenario)?;
+    printer.emit(Event::Report { report: &run::report(&tags, &scenario_tag_path, printer)? });
+    //the report is the output, so there's nothing more to say
+    Ok(String::new())
+}
+
+pub fn run_restore(tags_dir: &Path, timestamp: Option<&str>, printer: &Printer) -> Result<String, Error> {
+    let timestamp = restore_backup(tags_dir, timestamp, printer)?;
+    Ok(format!("Backup {} restored successfully", timestamp))
+}
+
+pub fn run_backups(tags_dir: &Path, matches: &ArgMatches, printer: &Printer) -> Result<String, Error> {
+    match matches.subcommand() {
+        Some(("list", _)) => {
+            let backups = list_backups(tags_dir)?;
+            backups.iter().for_each(|backup| {
+                progress!(printer, "{}", backup.timestamp);
+                backup.record.replaced.iter().for_each(|file| progress!(printer, "  replaced {}", file));
+                backup.record.created.iter().for_each(|file| progress!(printer, "  created {}", file));
+            });
+            Ok(format!("{} backup(s) found", backups.len()))
+        },
+        Some(("prune", prune_matches)) => {
+            let raw_keep = prune_matches.get_one::<String>("keep").unwrap();
+            let keep = usize::from_str(raw_keep).map_err(|_| Error::Options(format!("Not a valid number of backups: {}", raw_keep)))?;
+            let pruned = prune_backups(tags_dir, keep, printer)?;
+            Ok(format!("{} backup(s) deleted", pruned))
+        },
+        _ => unreachable!("backups requires a subcommand"),
+    }
+}
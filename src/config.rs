@@ -0,0 +1,313 @@
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use clap::ArgMatches;
+use clap::parser::ValueSource;
+use ringhopper::primitives::primitive::TagPath;
+use toml::{Table, Value};
+use spawn_camp::error::Error;
+
+pub const CONFIG_FILE_NAME: &str = "spawn-camp.toml";
+
+//arguments which can be set in spawn-camp.toml, by argument id and key. keys match the long option names
+pub const CONFIG_KEYS: &[(&str, &str)] = &[
+    ("tags", "tags"),
+    ("output-tags", "output-tags"),
+    ("marker-tag-path", "marker"),
+    ("marker-type", "marker-type"),
+    ("marker-red-tag-path", "marker-red"),
+    ("marker-blue-tag-path", "marker-blue"),
+    ("create-marker", "create-marker"),
+    ("name-markers", "name-markers"),
+    ("ground-snap", "ground-snap"),
+    ("snap-tolerance", "snap-tolerance"),
+    ("ground-report", "ground-report"),
+    ("lm-scale", "scale"),
+    ("max-size", "max-size"),
+    ("max-dimension", "max-dimension"),
+    ("randoms-color", "color"),
+    ("blend", "blend"),
+    ("dither", "dither"),
+    ("walkable", "walkable"),
+];
+//directories are shared by every scenario, and are relative to the config file rather than the working directory
+const PATH_KEYS: &[&str] = &["tags", "output-tags"];
+const SCENARIOS_KEY: &str = "scenarios";
+
+pub struct Config {
+    pub path: PathBuf,
+    defaults: Table,
+    //per-scenario overrides keyed by lowercase scenario tag path
+    scenarios: Vec<(String, Table)>,
+}
+
+#[derive(PartialEq)]
+pub enum ArgSource {
+    CommandLine,
+    Scenario,
+    Config,
+    Default,
+}
+
+impl ArgSource {
+    pub fn describe(&self) -> &'static str {
+        match self {
+            ArgSource::CommandLine => "command line",
+            ArgSource::Scenario => "scenario override",
+            ArgSource::Config => CONFIG_FILE_NAME,
+            ArgSource::Default => "default",
+        }
+    }
+}
+
+//looks in the working directory, then each parent directory
+pub fn find_config() -> Result<Option<Config>, Error> {
+    let working_dir = env::current_dir().map_err(|err| Error::io(format!("Could not get the working directory: {}", err), err))?;
+    match working_dir.ancestors().map(|dir| dir.join(CONFIG_FILE_NAME)).find(|path| path.exists()) {
+        Some(path) => read_config(&path).map(Some),
+        None => Ok(None),
+    }
+}
+
+fn read_config(path: &Path) -> Result<Config, Error> {
+    let contents = fs::read_to_string(path).map_err(|err| Error::io(format!("Could not read config {}: {}", path.display(), err), err))?;
+    let mut defaults: Table = contents.parse().map_err(|err| Error::Options(format!("Could not parse config {}: {}", path.display(), err)))?;
+    let config_dir = path.parent().unwrap_or(Path::new(""));
+
+    let mut scenarios: Vec<(String, Table)> = Vec::new();
+    if let Some(scenario_tables) = defaults.remove(SCENARIOS_KEY) {
+        let scenario_tables = match scenario_tables {
+            Value::Table(scenario_tables) => scenario_tables,
+            _ => return Err(Error::Options(format!("Expected [{}.\"<scenario-tag-path>\"] tables in config {}", SCENARIOS_KEY, path.display()))),
+        };
+        for (scenario, overrides) in scenario_tables {
+            let overrides = match overrides {
+                Value::Table(overrides) => overrides,
+                _ => return Err(Error::Options(format!("Expected a table of overrides for scenario {} in config {}", scenario, path.display()))),
+            };
+            check_keys(&overrides, path)?;
+            if let Some(key) = overrides.keys().find(|key| PATH_KEYS.contains(&key.as_str())) {
+                return Err(Error::Options(format!("{} can't be overridden for scenario {} in config {}", key, scenario, path.display())));
+            }
+            scenarios.push((normalize_scenario(&scenario), overrides));
+        }
+    }
+
+    check_keys(&defaults, path)?;
+    for key in PATH_KEYS {
+        if let Some(value) = defaults.get_mut(*key) {
+            resolve_paths(value, config_dir);
+        }
+    }
+
+    Ok(Config {
+        path: path.to_owned(),
+        defaults,
+        scenarios,
+    })
+}
+
+//argument values with command line flags taking precedence over scenario overrides, then config defaults, then built-in defaults
+pub struct ResolvedArgs<'a> {
+    matches: &'a ArgMatches,
+    config: Option<&'a Config>,
+    scenario: Option<&'a Table>,
+}
+
+impl<'a> ResolvedArgs<'a> {
+    pub fn new(matches: &'a ArgMatches, config: Option<&'a Config>, scenario_tag_path: Option<&TagPath>) -> ResolvedArgs<'a> {
+        let scenario = config.zip(scenario_tag_path).and_then(|(config, scenario_tag_path)| {
+            let scenario = normalize_scenario(scenario_tag_path.path());
+            config.scenarios.iter().find(|(path, _)| *path == scenario).map(|(_, overrides)| overrides)
+        });
+        ResolvedArgs {
+            matches,
+            config,
+            scenario,
+        }
+    }
+
+    pub fn get_one(&self, id: &str) -> Result<Option<String>, Error> {
+        match self.get_config_value(id) {
+            Some((key, value)) => self.value_to_string(key, value).map(Some),
+            None => Ok(self.matches.get_one::<String>(id).cloned()),
+        }
+    }
+
+    pub fn get_many(&self, id: &str) -> Result<Vec<String>, Error> {
+        match self.get_config_value(id) {
+            Some((key, Value::Array(values))) => values.iter().map(|value| self.value_to_string(key, value)).collect(),
+            Some((key, value)) => Ok(vec![self.value_to_string(key, value)?]),
+            None => Ok(self.matches.get_many::<String>(id).unwrap_or_default().cloned().collect()),
+        }
+    }
+
+    pub fn get_flag(&self, id: &str) -> Result<bool, Error> {
+        match self.get_config_value(id) {
+            Some((_, Value::Boolean(flag))) => Ok(*flag),
+            Some((key, _)) => Err(Error::Options(format!("Expected true or false for {} in config {}", key, self.config_path()))),
+            None => Ok(self.matches.get_flag(id)),
+        }
+    }
+
+    pub fn get_source(&self, id: &str) -> ArgSource {
+        if self.matches.value_source(id) == Some(ValueSource::CommandLine) || self.is_negated(id) {
+            return ArgSource::CommandLine;
+        }
+        let key = get_config_key(id);
+        if self.scenario.is_some_and(|overrides| overrides.contains_key(key)) {
+            ArgSource::Scenario
+        } else if self.config.is_some_and(|config| config.defaults.contains_key(key)) {
+            ArgSource::Config
+        } else {
+            ArgSource::Default
+        }
+    }
+
+    //flags can be turned off on the command line with --no-<flag>, in which case the flag itself reads as off
+    fn is_negated(&self, id: &str) -> bool {
+        matches!(self.matches.try_get_one::<bool>(&format!("no-{}", id)), Ok(Some(true)))
+    }
+
+    fn get_config_value(&self, id: &str) -> Option<(&'static str, &'a Value)> {
+        let key = get_config_key(id);
+        match self.get_source(id) {
+            ArgSource::Scenario => self.scenario.and_then(|overrides| overrides.get(key)).map(|value| (key, value)),
+            ArgSource::Config => self.config.and_then(|config| config.defaults.get(key)).map(|value| (key, value)),
+            _ => None,
+        }
+    }
+
+    fn value_to_string(&self, key: &str, value: &Value) -> Result<String, Error> {
+        match value {
+            Value::String(value) => Ok(value.clone()),
+            Value::Integer(value) => Ok(value.to_string()),
+            Value::Float(value) => Ok(value.to_string()),
+            _ => Err(Error::Options(format!("Expected a string or number for {} in config {}", key, self.config_path()))),
+        }
+    }
+
+    fn config_path(&self) -> String {
+        self.config.map(|config| config.path.display().to_string()).unwrap_or_default()
+    }
+}
+
+//every configurable argument is listed in CONFIG_KEYS, so a missing key is a programming error
+fn get_config_key(id: &str) -> &'static str {
+    CONFIG_KEYS.iter().find(|(arg_id, _)| *arg_id == id).map(|(_, key)| *key).expect("argument can't be set in the config")
+}
+
+fn check_keys(table: &Table, path: &Path) -> Result<(), Error> {
+    match table.keys().find(|key| !CONFIG_KEYS.iter().any(|(_, config_key)| config_key == key)) {
+        Some(key) => Err(Error::Options(format!("Unknown option {} in config {}", key, path.display()))),
+        None => Ok(()),
+    }
+}
+
+fn resolve_paths(value: &mut Value, config_dir: &Path) {
+    match value {
+        Value::String(path) => *path = config_dir.join(&*path).to_string_lossy().into_owned(),
+        Value::Array(values) => values.iter_mut().for_each(|value| resolve_paths(value, config_dir)),
+        _ => (),
+    }
+}
+
+fn normalize_scenario(scenario: &str) -> String {
+    scenario.replace('/', "\\").to_ascii_lowercase()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::process;
+    use clap::Command;
+    use ringhopper::primitives::primitive::TagGroup;
+    use super::*;
+    use crate::cli::{marker_args, output_tags_arg, render_args};
+
+    const CONFIG: &str = r#"
+output-tags = "out"
+scale = 2
+ground-snap = true
+dither = "ordered"
+
+[scenarios."levels/test/chillout/chillout"]
+scale = 8
+"#;
+
+    fn write_config(name: &str, contents: &str) -> PathBuf {
+        let dir = env::temp_dir().join(format!("spawn-camp-{}-{}", name, process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join(CONFIG_FILE_NAME);
+        fs::write(&path, contents).unwrap();
+        path
+    }
+
+    fn get_matches(args: &[&str]) -> ArgMatches {
+        Command::new("apply")
+            .arg(output_tags_arg())
+            .args(marker_args())
+            .args(render_args())
+            .get_matches_from(std::iter::once("apply").chain(args.iter().copied()))
+    }
+
+    fn chillout() -> TagPath {
+        TagPath::new("levels\\test\\chillout\\chillout", TagGroup::Scenario).unwrap()
+    }
+
+    #[test]
+    fn command_line_beats_scenario_beats_config_beats_default() {
+        let path = write_config("config-precedence", CONFIG);
+        let config = read_config(&path).unwrap();
+        let matches = get_matches(&[]);
+        let scenario = chillout();
+
+        let args = ResolvedArgs::new(&matches, Some(&config), None);
+        assert_eq!(args.get_one("lm-scale").unwrap().as_deref(), Some("2"));
+        assert!(args.get_source("lm-scale") == ArgSource::Config);
+        assert!(args.get_source("blend") == ArgSource::Default);
+
+        let args = ResolvedArgs::new(&matches, Some(&config), Some(&scenario));
+        assert_eq!(args.get_one("lm-scale").unwrap().as_deref(), Some("8"));
+        assert!(args.get_source("lm-scale") == ArgSource::Scenario);
+        assert_eq!(args.get_one("dither").unwrap().as_deref(), Some("ordered"));
+
+        let matches = get_matches(&["--scale", "1"]);
+        let args = ResolvedArgs::new(&matches, Some(&config), Some(&scenario));
+        assert_eq!(args.get_one("lm-scale").unwrap().as_deref(), Some("1"));
+        assert!(args.get_source("lm-scale") == ArgSource::CommandLine);
+        fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+
+    #[test]
+    fn no_flag_turns_off_config_flag() {
+        let path = write_config("config-no-flag", CONFIG);
+        let config = read_config(&path).unwrap();
+
+        let matches = get_matches(&[]);
+        assert!(ResolvedArgs::new(&matches, Some(&config), None).get_flag("ground-snap").unwrap());
+        let matches = get_matches(&["--no-ground-snap"]);
+        let args = ResolvedArgs::new(&matches, Some(&config), None);
+        assert!(!args.get_flag("ground-snap").unwrap());
+        assert!(args.get_source("ground-snap") == ArgSource::CommandLine);
+        fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+
+    #[test]
+    fn directories_are_relative_to_the_config() {
+        let path = write_config("config-paths", CONFIG);
+        let config = read_config(&path).unwrap();
+        let matches = get_matches(&[]);
+        let args = ResolvedArgs::new(&matches, Some(&config), None);
+        assert_eq!(args.get_one("output-tags").unwrap().map(PathBuf::from), Some(path.parent().unwrap().join("out")));
+        fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+
+    #[test]
+    fn bad_keys_are_errors() {
+        let path = write_config("config-unknown-key", "colour = \"FF0000\"\n");
+        assert!(read_config(&path).is_err());
+        fs::write(&path, "[scenarios.\"levels\\\\test\\\\chillout\\\\chillout\"]\ntags = \"tags\"\n").unwrap();
+        assert!(read_config(&path).is_err());
+        fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+}
@@ -0,0 +1,141 @@
+use ringhopper::definitions::BitmapDataFormat;
+use crate::lm_bitmap::LmPage;
+
+/// Dithering used when reducing rendered pages to the lightmap's 16-bit color.
+#[derive(Copy, Clone)]
+pub enum DitherMode {
+    /// Rounds each pixel to the nearest color, giving the same output every time.
+    None,
+    /// A 4x4 Bayer matrix, which doesn't spread errors between pixels.
+    Ordered,
+    /// Floyd-Steinberg error diffusion.
+    Diffusion,
+}
+
+const BAYER_4X4: [[u8; 4]; 4] = [
+    [0, 8, 2, 10],
+    [12, 4, 14, 6],
+    [3, 11, 1, 9],
+    [15, 7, 13, 5],
+];
+
+//bits per channel in R5G6B5, ordered the same as the rendered A8R8G8B8 bytes (B, G, R)
+const CHANNEL_BITS: [u32; 3] = [5, 6, 5];
+
+/// Converts a page rendered as A8R8G8B8 to R5G6B5, optionally dithering to hide banding.
+pub(crate) fn quantize_page(page: &LmPage, mode: DitherMode) -> LmPage {
+    let w = page.dimensions.w as usize;
+    let h = page.dimensions.h as usize;
+
+    //channel values scaled to the target bit depth, before rounding
+    let mut levels: Vec<[f32; 3]> = page.data.chunks_exact(4).map(|bgra| {
+        std::array::from_fn(|c| bgra[c] as f32 / 255.0 * max_level(c))
+    }).collect();
+
+    let mut quantized: Vec<[u16; 3]> = vec![[0; 3]; w * h];
+    for y in 0..h {
+        for x in 0..w {
+            let i = y * w + x;
+            for c in 0..3 {
+                let value = levels[i][c];
+                let q = match mode {
+                    DitherMode::None => value.round(),
+                    DitherMode::Ordered => {
+                        let threshold = (BAYER_4X4[y % 4][x % 4] as f32 + 0.5) / 16.0;
+                        (value + threshold - 0.5).round()
+                    },
+                    DitherMode::Diffusion => value.round(),
+                }.clamp(0.0, max_level(c));
+                quantized[i][c] = q as u16;
+
+                //floyd-steinberg: push the rounding error onto unvisited neighbours
+                if let DitherMode::Diffusion = mode {
+                    let error = value - q;
+                    if x + 1 < w {
+                        levels[i + 1][c] += error * 7.0 / 16.0;
+                    }
+                    if y + 1 < h {
+                        if x > 0 {
+                            levels[i + w - 1][c] += error * 3.0 / 16.0;
+                        }
+                        levels[i + w][c] += error * 5.0 / 16.0;
+                        if x + 1 < w {
+                            levels[i + w + 1][c] += error * 1.0 / 16.0;
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    let data: Vec<u8> = quantized.iter().flat_map(|[b, g, r]| {
+        ((r << 11) | (g << 5) | b).to_le_bytes()
+    }).collect();
+
+    LmPage {
+        dimensions: page.dimensions,
+        data_format: BitmapDataFormat::R5G6B5,
+        data,
+    }
+}
+
+fn max_level(channel: usize) -> f32 {
+    ((1u32 << CHANNEL_BITS[channel]) - 1) as f32
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lm_bitmap::Dimensions;
+
+    fn flat_page(w: u16, h: u16, bgra: [u8; 4]) -> LmPage {
+        LmPage {
+            dimensions: Dimensions { w, h },
+            data_format: BitmapDataFormat::A8R8G8B8,
+            data: bgra.repeat(w as usize * h as usize),
+        }
+    }
+
+    fn get_pixels(page: &LmPage) -> Vec<u16> {
+        page.data.chunks_exact(2).map(|bytes| u16::from_le_bytes([bytes[0], bytes[1]])).collect()
+    }
+
+    //average blue level of the quantized page, scaled back to 0-255
+    fn get_average_blue(page: &LmPage) -> f32 {
+        let pixels = get_pixels(page);
+        pixels.iter().map(|pixel| (pixel & 0x1F) as f32 * 255.0 / 31.0).sum::<f32>() / pixels.len() as f32
+    }
+
+    #[test]
+    fn extremes_are_exact_in_every_mode() {
+        for mode in [DitherMode::None, DitherMode::Ordered, DitherMode::Diffusion] {
+            assert!(get_pixels(&quantize_page(&flat_page(4, 4, [255, 255, 255, 255]), mode)).iter().all(|pixel| *pixel == 0xFFFF));
+            assert!(get_pixels(&quantize_page(&flat_page(4, 4, [0, 0, 0, 255]), mode)).iter().all(|pixel| *pixel == 0));
+        }
+    }
+
+    #[test]
+    fn channels_are_packed_as_r5g6b5() {
+        let quantized = quantize_page(&flat_page(1, 1, [255, 0, 0, 255]), DitherMode::None);
+        assert_eq!(quantized.data_format, BitmapDataFormat::R5G6B5);
+        assert_eq!(get_pixels(&quantized), vec![0x001F]);
+    }
+
+    #[test]
+    fn no_dithering_gives_a_flat_page() {
+        let quantized = quantize_page(&flat_page(8, 8, [100, 100, 100, 255]), DitherMode::None);
+        let pixels = get_pixels(&quantized);
+        assert!(pixels.iter().all(|pixel| *pixel == pixels[0]));
+    }
+
+    #[test]
+    fn dithering_keeps_the_average_between_levels() {
+        //100 falls between two 5-bit levels, 98.7 and 106.9
+        for mode in [DitherMode::Ordered, DitherMode::Diffusion] {
+            let quantized = quantize_page(&flat_page(8, 8, [100, 100, 100, 255]), mode);
+            let pixels = get_pixels(&quantized);
+            assert!(pixels.iter().any(|pixel| *pixel != pixels[0]));
+            assert!((get_average_blue(&quantized) - 100.0).abs() < 1.0);
+        }
+    }
+}
@@ -0,0 +1,180 @@
+use std::error::Error as StdError;
+use std::fmt;
+
+//each category has its own exit code so build scripts can tell failures apart. codes and kinds are relied on
+//by scripts, so they should only ever be added to, not changed
+/// Everything which can go wrong. Each variant's message is complete on its own and shown as-is.
+#[derive(Debug)]
+pub enum Error {
+    /// Invalid command line arguments or config options.
+    Options(String),
+    /// Files other than tags: the config, manifests, backups, plans and exports.
+    Io {
+        /// What went wrong, including the underlying error's message.
+        message: String,
+        /// The underlying error, like the `std::io::Error` of a failed read, if there was one.
+        source: Option<Box<dyn StdError + Send + Sync>>,
+    },
+    /// A tag which exists couldn't be read, or a tag couldn't be written or deleted.
+    TagIo {
+        /// Tag path of the tag.
+        tag: String,
+        /// What went wrong.
+        message: String,
+    },
+    /// A tag doesn't exist, or a tag doesn't reference one it needs to, like a scenario without a BSP.
+    MissingTag {
+        /// Tag path of the missing tag, or of the tag missing a reference.
+        tag: String,
+        /// What went wrong.
+        message: String,
+    },
+    /// BSP data which can't be rendered, with the lightmap, material and surface it was found in.
+    MalformedBsp {
+        /// Tag path of the BSP.
+        bsp: String,
+        /// Index of the BSP lightmap, if the problem is in one.
+        lightmap: Option<usize>,
+        /// Index of the material within the lightmap, if the problem is in one.
+        material: Option<usize>,
+        /// Index of the BSP surface, if the problem is in one.
+        surface: Option<usize>,
+        /// What went wrong.
+        message: String,
+    },
+    /// Lightmap bitmaps with unsupported formats, bad pixel data or missing pages.
+    InvalidLightmap {
+        /// Tag path of the bitmap.
+        tag: String,
+        /// Bitmap data index of the page, if the problem is in one.
+        page: Option<u16>,
+        /// What went wrong.
+        message: String,
+    },
+    /// Vulkan couldn't be set up or a lightmap page couldn't be rendered.
+    Renderer {
+        /// Bitmap data index of the page being rendered, if any.
+        page: Option<u16>,
+        /// What went wrong.
+        message: String,
+    },
+    /// Some scenarios in a batch failed, each having been reported already.
+    ScenariosFailed {
+        /// How many scenarios failed.
+        failed: usize,
+        /// How many scenarios were in the batch.
+        total: usize,
+    },
+}
+
+impl Error {
+    /// The process exit code for the error, from 2 upwards.
+    pub fn exit_code(&self) -> u8 {
+        match self {
+            //matches clap's exit code for invalid arguments
+            Error::Options(_) => 2,
+            Error::Io { .. } => 3,
+            Error::TagIo { .. } => 4,
+            Error::MissingTag { .. } => 5,
+            Error::MalformedBsp { .. } => 6,
+            Error::InvalidLightmap { .. } => 7,
+            Error::Renderer { .. } => 8,
+            Error::ScenariosFailed { .. } => 9,
+        }
+    }
+
+    /// The kind of error in `--output json`, like `missing_tag`.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Error::Options(_) => "options",
+            Error::Io { .. } => "io",
+            Error::TagIo { .. } => "tag_io",
+            Error::MissingTag { .. } => "missing_tag",
+            Error::MalformedBsp { .. } => "malformed_bsp",
+            Error::InvalidLightmap { .. } => "invalid_lightmap",
+            Error::Renderer { .. } => "renderer",
+            Error::ScenariosFailed { .. } => "scenarios_failed",
+        }
+    }
+
+    /// Tag path of the tag the error is about, if any.
+    pub fn tag(&self) -> Option<&str> {
+        match self {
+            Error::TagIo { tag, .. } | Error::MissingTag { tag, .. } | Error::InvalidLightmap { tag, .. } => Some(tag),
+            Error::MalformedBsp { bsp, .. } => Some(bsp),
+            _ => None,
+        }
+    }
+
+    /// Bitmap data index of the lightmap page the error is about, if any.
+    pub fn page(&self) -> Option<u16> {
+        match self {
+            Error::InvalidLightmap { page, .. } | Error::Renderer { page, .. } => *page,
+            _ => None,
+        }
+    }
+
+    /// An [`Error::Io`] caused by another error, whose message should already be part of `message`.
+    pub fn io(message: String, source: impl Into<Box<dyn StdError + Send + Sync>>) -> Error {
+        Error::Io { message, source: Some(source.into()) }
+    }
+
+    //the renderer doesn't know which page it's given, so the caller fills it in
+    pub(crate) fn with_page(self, page: u16) -> Error {
+        match self {
+            Error::Renderer { message, .. } => Error::Renderer { page: Some(page), message },
+            Error::InvalidLightmap { tag, message, .. } => Error::InvalidLightmap { tag, page: Some(page), message },
+            err => err,
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Options(message) | Error::Io { message, .. } | Error::TagIo { message, .. } | Error::MissingTag { message, .. } => write!(f, "{}", message),
+            Error::MalformedBsp { bsp, lightmap, material, surface, message } => {
+                write!(f, "The BSP {} is malformed", bsp)?;
+                if let Some(lightmap) = lightmap {
+                    write!(f, " in lightmap {}", lightmap)?;
+                }
+                if let Some(material) = material {
+                    write!(f, " material {}", material)?;
+                }
+                if let Some(surface) = surface {
+                    write!(f, " surface {}", surface)?;
+                }
+                write!(f, ": {}", message)
+            },
+            Error::InvalidLightmap { tag, page: Some(page), message } => write!(f, "The lightmap bitmap {} page {} can't be rendered: {}", tag, page, message),
+            Error::InvalidLightmap { message, .. } => write!(f, "{}", message),
+            Error::Renderer { page: Some(page), message } => write!(f, "Failed to render lightmap page {}: {}", page, message),
+            Error::Renderer { message, .. } => write!(f, "{}", message),
+            Error::ScenariosFailed { failed, total } => write!(f, "{} of {} scenarios failed", failed, total),
+        }
+    }
+}
+
+impl StdError for Error {
+    fn source(&self) -> Option<&(dyn StdError + 'static)> {
+        match self {
+            Error::Io { source, .. } => source.as_deref().map(|source| source as &(dyn StdError + 'static)),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io;
+
+    #[test]
+    fn io_errors_keep_their_source() {
+        let err = Error::io("Could not read manifest: not found".into(), io::Error::from(io::ErrorKind::NotFound));
+        let source = err.source().and_then(|source| source.downcast_ref::<io::Error>()).unwrap();
+        assert_eq!(source.kind(), io::ErrorKind::NotFound);
+        assert_eq!(err.to_string(), "Could not read manifest: not found");
+        assert!(Error::Options("bad option".into()).source().is_none());
+    }
+}
@@ -0,0 +1,62 @@
+use std::fs;
+use std::path::Path;
+use serde::Serialize;
+use ringhopper::definitions::ScenarioSpawnType;
+use crate::spawns::SpawnInfo;
+use crate::error::Error;
+use crate::markers::get_spawn_type_label;
+use crate::output::{Event, EventSink};
+
+//field names are kept stable since other tools read these files
+/// A scenario's spawns, written as JSON for other tools.
+#[derive(Serialize)]
+pub struct SpawnExport {
+    /// Tag path of the scenario.
+    pub scenario: String,
+    /// Every player starting location, in order.
+    pub spawns: Vec<SpawnRecord>,
+}
+
+/// A player starting location.
+#[derive(Serialize)]
+pub struct SpawnRecord {
+    /// Index in the scenario's player starting locations.
+    pub index: usize,
+    /// World position.
+    pub position: [f32; 3],
+    /// Facing in degrees.
+    pub facing_degrees: f32,
+    /// Team index, where 0 is red and 1 is blue.
+    pub team_index: u16,
+    /// Labels as accepted by `--marker-type`, without empty type slots.
+    pub types: Vec<&'static str>,
+}
+
+impl SpawnExport {
+    /// Exports the spawns, which are usually every spawn in the scenario.
+    pub fn new(scenario: &str, spawns: &[SpawnInfo]) -> SpawnExport {
+        SpawnExport {
+            scenario: scenario.to_owned(),
+            spawns: spawns.iter().map(|spawn| SpawnRecord {
+                index: spawn.index,
+                position: [spawn.position.x, spawn.position.y, spawn.position.z],
+                facing_degrees: spawn.facing.to_degrees(),
+                team_index: spawn.team_index,
+                types: spawn.types.iter()
+                    .filter(|spawn_type| **spawn_type != ScenarioSpawnType::None)
+                    .map(|spawn_type| get_spawn_type_label(*spawn_type))
+                    .collect(),
+            }).collect(),
+        }
+    }
+}
+
+/// Writes the export to a file as JSON, or emits it as an event when no file is given so the output can be piped.
+pub fn write_spawn_export(export: &SpawnExport, path: Option<&Path>, sink: &dyn EventSink) -> Result<(), Error> {
+    let Some(path) = path else {
+        sink.emit(Event::Export { export });
+        return Ok(());
+    };
+    let contents = serde_json::to_string_pretty(export).map_err(|err| Error::io(format!("Could not serialize spawns: {}", err), err))?;
+    fs::write(path, contents).map_err(|err| Error::io(format!("Could not write spawns to {}: {}", path.display(), err), err))
+}
@@ -0,0 +1,111 @@
+use ringhopper::definitions::{GBXModel, GBXModelGeometry, GBXModelGeometryPart, GBXModelRegion, GBXModelRegionPermutation, GBXModelShaderReference, ModelNode, ModelTriangle, ModelVertexUncompressed, Scenery, ShaderModel};
+use ringhopper::primitives::primitive::{Point2D, Point3D, Quaternion, Reflexive, String32, TagGroup, TagPath, TagReference, Vector3D};
+use crate::tags::{write_tag, LayeredTags};
+use crate::error::Error;
+use crate::plan::ChangePlan;
+use crate::output::{progress, EventSink};
+
+//a flat arrow lying just above the ground and pointing along the spawn's facing (+X)
+const ARROW_HEIGHT: f32 = 0.01;
+const ARROW_POINTS: [[f32; 2]; 7] = [
+    //shaft
+    [0.0, -0.05],
+    [0.0, 0.05],
+    [0.3, -0.05],
+    [0.3, 0.05],
+    //head
+    [0.3, -0.15],
+    [0.3, 0.15],
+    [0.5, 0.0],
+];
+//triangle strip covering the shaft then the head, joined by degenerate triangles
+const ARROW_STRIP: [u16; 9] = [0, 1, 2, 3, 3, 4, 4, 5, 6];
+
+//writes a minimal scenery, gbxmodel and shader_model sharing the marker's tag path
+pub fn create_fallback_marker(tags: &mut LayeredTags, marker_tag_path: &TagPath, plan: &mut ChangePlan, sink: &dyn EventSink) -> Result<(), Error> {
+    progress!(sink, "Creating fallback marker scenery {}", marker_tag_path);
+    let model_tag_path = TagPath::new(marker_tag_path.path(), TagGroup::GBXModel).map_err(|_| Error::Options(format!("Not a valid tag path: {}", marker_tag_path)))?;
+    let shader_tag_path = TagPath::new(marker_tag_path.path(), TagGroup::ShaderModel).map_err(|_| Error::Options(format!("Not a valid tag path: {}", marker_tag_path)))?;
+
+    let mut shader = ShaderModel::default();
+    //the arrow is flat, so it has to be visible from below as well
+    shader.flags.two_sided = true;
+    write_tag(tags, &shader_tag_path, &shader, plan, sink)?;
+
+    let model = create_arrow_model(&model_tag_path, &shader_tag_path)?;
+    write_tag(tags, &model_tag_path, &model, plan, sink)?;
+
+    let mut scenery = Scenery::default();
+    scenery.base_struct.model = TagReference::Set(model_tag_path);
+    scenery.base_struct.bounding_radius = 0.5;
+    write_tag(tags, marker_tag_path, &scenery, plan, sink)?;
+
+    Ok(())
+}
+
+fn create_arrow_model(model_tag_path: &TagPath, shader_tag_path: &TagPath) -> Result<GBXModel, Error> {
+    let name_err = |name: &str| Error::TagIo { tag: model_tag_path.to_string(), message: format!("Failed to create model name {}", name) };
+    let base_name = String32::from_str("__base").map_err(|_| name_err("__base"))?;
+
+    let vertices: Vec<ModelVertexUncompressed> = ARROW_POINTS.iter().map(|[x, y]| {
+        ModelVertexUncompressed {
+            position: Point3D { x: *x, y: *y, z: ARROW_HEIGHT },
+            normal: Vector3D { x: 0.0, y: 0.0, z: 1.0 },
+            binormal: Vector3D { x: 0.0, y: 1.0, z: 0.0 },
+            tangent: Vector3D { x: 1.0, y: 0.0, z: 0.0 },
+            texture_coords: Point2D { x: *x, y: *y },
+            node0_index: Some(0),
+            node1_index: None,
+            node0_weight: 1.0,
+            node1_weight: 0.0,
+            ..ModelVertexUncompressed::default()
+        }
+    }).collect();
+
+    let triangles: Vec<ModelTriangle> = ARROW_STRIP.chunks(3).map(|strip_indices| {
+        ModelTriangle {
+            vertex0_index: Some(strip_indices[0]),
+            vertex1_index: Some(strip_indices[1]),
+            vertex2_index: Some(strip_indices[2]),
+        }
+    }).collect();
+
+    Ok(GBXModel {
+        nodes: Reflexive::new(vec![ModelNode {
+            name: String32::from_str("frame").map_err(|_| name_err("frame"))?,
+            next_sibling_node_index: None,
+            first_child_node_index: None,
+            parent_node_index: None,
+            default_rotation: Quaternion { x: 0.0, y: 0.0, z: 0.0, w: 1.0 },
+            ..ModelNode::default()
+        }]),
+        regions: Reflexive::new(vec![GBXModelRegion {
+            name: base_name,
+            permutations: Reflexive::new(vec![GBXModelRegionPermutation {
+                name: base_name,
+                super_low: Some(0),
+                low: Some(0),
+                medium: Some(0),
+                high: Some(0),
+                super_high: Some(0),
+                ..GBXModelRegionPermutation::default()
+            }]),
+            ..GBXModelRegion::default()
+        }]),
+        geometries: Reflexive::new(vec![GBXModelGeometry {
+            parts: Reflexive::new(vec![GBXModelGeometryPart {
+                shader_index: Some(0),
+                centroid: Point3D { x: 0.25, y: 0.0, z: ARROW_HEIGHT },
+                uncompressed_vertices: Reflexive::new(vertices),
+                triangles: Reflexive::new(triangles),
+                ..GBXModelGeometryPart::default()
+            }]),
+            ..GBXModelGeometry::default()
+        }]),
+        shaders: Reflexive::new(vec![GBXModelShaderReference {
+            shader: TagReference::Set(shader_tag_path.clone()),
+            ..GBXModelShaderReference::default()
+        }]),
+        ..GBXModel::default()
+    })
+}
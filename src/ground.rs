@@ -0,0 +1,151 @@
+use ringhopper::definitions::ModelCollisionGeometryBSP;
+use ringhopper::primitives::primitive::Vector3D;
+use crate::spawns::SpawnInfo;
+use crate::output::{warning, EventSink};
+
+/// How spawn markers are moved onto the ground.
+pub struct GroundSnapping {
+    /// How far above and below each spawn to search for ground.
+    pub tolerance: f32,
+    /// Spawns further than this from the ground are reported.
+    pub report_distance: f32,
+}
+
+/// Returns copies of the spawns with their height moved onto the collision geometry below them.
+pub(crate) fn snap_to_ground(spawns: &[SpawnInfo], collision_bsp: &ModelCollisionGeometryBSP, snapping: &GroundSnapping, sink: &dyn EventSink) -> Vec<SpawnInfo> {
+    let surfaces = get_surface_polygons(collision_bsp);
+
+    spawns.iter().map(|spawn| {
+        let position = spawn.position;
+        match find_ground_height(&surfaces, position, snapping.tolerance) {
+            Some(ground_height) => {
+                let distance = position.z - ground_height;
+                if distance.abs() > snapping.report_distance {
+                    let relation = if distance > 0.0 { "above" } else { "below" };
+                    warning!(sink, "Spawn {} at ({:.3}, {:.3}, {:.3}) is {:.3} units {} the ground", spawn.index, position.x, position.y, position.z, distance.abs(), relation);
+                }
+                SpawnInfo {
+                    position: Vector3D { z: ground_height, ..position },
+                    ..spawn.clone()
+                }
+            },
+            None => {
+                warning!(sink, "Spawn {} at ({:.3}, {:.3}, {:.3}) has no ground within {} units, leaving its marker in place", spawn.index, position.x, position.y, position.z, snapping.tolerance);
+                spawn.clone()
+            }
+        }
+    }).collect()
+}
+
+//nearest surface at or below the position crossed by a vertical ray through it, so a spawn under a bridge or ledge
+//isn't snapped on top of it. the nearest surface above is only used if there's none below, for sunken spawns
+fn find_ground_height(surfaces: &[Vec<[f32; 3]>], position: Vector3D, tolerance: f32) -> Option<f32> {
+    let (x, y, z) = (position.x, position.y, position.z);
+    let heights: Vec<f32> = surfaces.iter()
+        .flat_map(|polygon| {
+            //fan triangulation is fine since collision surfaces are convex
+            (1..polygon.len().saturating_sub(1)).filter_map(move |i| {
+                intersect_vertical(x, y, polygon[0], polygon[i], polygon[i + 1])
+            })
+        })
+        .filter(|height| (height - z).abs() <= tolerance)
+        .collect();
+    let below = heights.iter().copied().filter(|height| *height <= z).reduce(f32::max);
+    let above = heights.iter().copied().filter(|height| *height > z).reduce(f32::min);
+    below.or(above)
+}
+
+fn intersect_vertical(x: f32, y: f32, a: [f32; 3], b: [f32; 3], c: [f32; 3]) -> Option<f32> {
+    //barycentric coordinates of the point projected onto the XY plane
+    let det = (b[1] - c[1]) * (a[0] - c[0]) + (c[0] - b[0]) * (a[1] - c[1]);
+    if det.abs() < f32::EPSILON {
+        //vertical walls can't be stood on
+        return None;
+    }
+    let u = ((b[1] - c[1]) * (x - c[0]) + (c[0] - b[0]) * (y - c[1])) / det;
+    let v = ((c[1] - a[1]) * (x - c[0]) + (a[0] - c[0]) * (y - c[1])) / det;
+    let w = 1.0 - u - v;
+    if u < 0.0 || v < 0.0 || w < 0.0 {
+        return None;
+    }
+    Some(u * a[2] + v * b[2] + w * c[2])
+}
+
+//walks each surface's edge loop to get its vertices
+fn get_surface_polygons(collision_bsp: &ModelCollisionGeometryBSP) -> Vec<Vec<[f32; 3]>> {
+    let edges = &collision_bsp.edges.items;
+    let vertices = &collision_bsp.vertices.items;
+
+    collision_bsp.surfaces.items.iter().enumerate().map(|(surface_index, surface)| {
+        let mut polygon: Vec<[f32; 3]> = Vec::new();
+        let mut edge_index = surface.first_edge as usize;
+        //bounded by the edge count in case the loop is malformed
+        for _ in 0..edges.len() {
+            let edge = match edges.get(edge_index) {
+                Some(edge) => edge,
+                None => break,
+            };
+            let (vertex_index, next_edge_index) = if edge.left_surface as usize == surface_index {
+                (edge.start_vertex, edge.forward_edge)
+            } else {
+                (edge.end_vertex, edge.reverse_edge)
+            };
+            if let Some(vertex) = vertices.get(vertex_index as usize) {
+                polygon.push([vertex.point.x, vertex.point.y, vertex.point.z]);
+            }
+            edge_index = next_edge_index as usize;
+            if edge_index == surface.first_edge as usize {
+                break;
+            }
+        }
+        polygon
+    }).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn square(z: f32) -> Vec<[f32; 3]> {
+        vec![[-1.0, -1.0, z], [1.0, -1.0, z], [1.0, 1.0, z], [-1.0, 1.0, z]]
+    }
+
+    fn position(x: f32, y: f32, z: f32) -> Vector3D {
+        Vector3D { x, y, z }
+    }
+
+    #[test]
+    fn vertical_ray_hits_sloped_triangle() {
+        let height = intersect_vertical(0.5, 0.0, [0.0, -1.0, 0.0], [1.0, -1.0, 1.0], [0.0, 1.0, 0.0]);
+        assert!((height.unwrap() - 0.5).abs() < 0.0001);
+    }
+
+    #[test]
+    fn vertical_ray_misses_outside_triangle() {
+        assert_eq!(intersect_vertical(2.0, 2.0, [0.0, 0.0, 0.0], [1.0, 0.0, 0.0], [0.0, 1.0, 0.0]), None);
+    }
+
+    #[test]
+    fn vertical_walls_are_ignored() {
+        assert_eq!(intersect_vertical(0.0, 0.0, [-1.0, 0.0, 0.0], [1.0, 0.0, 0.0], [0.0, 0.0, 1.0]), None);
+    }
+
+    #[test]
+    fn nearest_ground_below_wins_over_ledge_above() {
+        let surfaces = vec![square(0.0), square(5.0), square(-2.0)];
+        assert_eq!(find_ground_height(&surfaces, position(0.0, 0.0, 3.0), 10.0), Some(0.0));
+    }
+
+    #[test]
+    fn sunken_spawn_snaps_up() {
+        let surfaces = vec![square(0.0), square(5.0)];
+        assert_eq!(find_ground_height(&surfaces, position(0.0, 0.0, -0.5), 1.0), Some(0.0));
+    }
+
+    #[test]
+    fn ground_beyond_tolerance_is_ignored() {
+        let surfaces = vec![square(0.0)];
+        assert_eq!(find_ground_height(&surfaces, position(0.0, 0.0, 3.0), 1.0), None);
+        assert_eq!(find_ground_height(&surfaces, position(3.0, 0.0, 0.5), 1.0), None);
+    }
+}
@@ -0,0 +1,121 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use serde::Serialize;
+use ringhopper::primitives::primitive::{TagGroup, TagPath};
+use crate::tags::get_tag_file_path;
+use crate::error::Error;
+use crate::output::{progress, EventSink};
+
+/// Tags directories in priority order. Only the first is ever written to.
+#[derive(Serialize, Default, Clone)]
+pub struct TagsLayers {
+    /// The directory tags are written to, and read from first.
+    pub write: PathBuf,
+    /// Read-only layers such as stock HEK tags underneath a project's tags, highest priority first.
+    pub base: Vec<PathBuf>,
+    /// Whether the write directory is an output directory holding only what spawn-camp generates. Its copies of the
+    /// scenario and BSP are then never read as sources, so edits to the base layers' copies always take effect.
+    pub overlay: bool,
+}
+
+impl TagsLayers {
+    /// Fails if the write directory is also given as a base layer.
+    pub fn new(write: &Path, base: &[&Path]) -> Result<TagsLayers, Error> {
+        let layers = TagsLayers {
+            write: write.to_owned(),
+            base: base.iter().map(|dir| dir.to_path_buf()).collect(),
+            overlay: false,
+        };
+        //the same directory given twice would have its "read-only" copy written to
+        if let Some(base_dir) = layers.base.iter().find(|base_dir| is_same_dir(&layers.write, base_dir)) {
+            return Err(Error::Options(format!("The tags directory {} is written to, so it can't also be a read-only base layer ({})", layers.write.display(), base_dir.display())));
+        }
+        Ok(layers)
+    }
+
+    /// Layers for an output directory over the base tags directories, as with `--output-tags`.
+    pub fn new_overlay(output: &Path, base: &[&Path]) -> Result<TagsLayers, Error> {
+        Ok(TagsLayers { overlay: true, ..TagsLayers::new(output, base)? })
+    }
+
+    /// Every directory, highest priority first.
+    pub fn all(&self) -> Vec<&Path> {
+        std::iter::once(self.write.as_path()).chain(self.base.iter().map(PathBuf::as_path)).collect()
+    }
+
+    /// The directories the source scenario, BSP and lightmap are read from: the base layers with an output directory,
+    /// and otherwise every directory.
+    pub fn sources(&self) -> Vec<&Path> {
+        match self.overlay {
+            true => self.base.iter().map(PathBuf::as_path).collect(),
+            false => self.all(),
+        }
+    }
+
+    //the base layer a written tag will take precedence over, if any
+    pub(crate) fn find_base_layer(&self, tag_path: &TagPath) -> Option<&Path> {
+        let tag_file_path = get_tag_file_path(tag_path);
+        self.base.iter()
+            .find(|base_dir| base_dir.join(&tag_file_path).exists())
+            .map(PathBuf::as_path)
+    }
+
+    /// Lists the layers, if there's more than one.
+    pub fn print(&self, sink: &dyn EventSink) {
+        if self.base.is_empty() {
+            return;
+        }
+        progress!(sink, "Tags directory layers, highest priority first:");
+        match self.overlay {
+            true => progress!(sink, "  {} (output, written to)", self.write.display()),
+            false => progress!(sink, "  {} (written to)", self.write.display()),
+        }
+        self.base.iter().for_each(|base_dir| progress!(sink, "  {} (read-only)", base_dir.display()));
+    }
+}
+
+/// Every tag of the group in any of the directories, without duplicates.
+pub fn find_tags(tags_dirs: &[&Path], group: TagGroup) -> Result<Vec<TagPath>, Error> {
+    let mut tag_paths: Vec<TagPath> = Vec::new();
+    for tags_dir in tags_dirs {
+        let mut files: Vec<PathBuf> = Vec::new();
+        find_tag_files(tags_dir, tags_dir, &mut files)?;
+        for file in files.iter().filter(|file| file.extension().is_some_and(|extension| extension == group.as_str())) {
+            let tag_path = get_file_tag_path(file, group)?;
+            if !tag_paths.contains(&tag_path) {
+                tag_paths.push(tag_path);
+            }
+        }
+    }
+    Ok(tag_paths)
+}
+
+//collects every file under the directory, relative to the tags directory
+pub(crate) fn find_tag_files(dir: &Path, tags_dir: &Path, files: &mut Vec<PathBuf>) -> Result<(), Error> {
+    let entries = fs::read_dir(dir).map_err(|err| Error::io(format!("Could not read directory {}: {}", dir.display(), err), err))?;
+    for entry in entries {
+        let path = entry.map_err(|err| Error::io(format!("Could not read directory {}: {}", dir.display(), err), err))?.path();
+        if path.is_dir() {
+            find_tag_files(&path, tags_dir, files)?;
+        } else if let Ok(relative_path) = path.strip_prefix(tags_dir) {
+            files.push(relative_path.to_owned());
+        }
+    }
+    Ok(())
+}
+
+//levels/test/chillout/chillout.scenario_structure_bsp is the tag levels\test\chillout\chillout
+fn get_file_tag_path(file: &Path, group: TagGroup) -> Result<TagPath, Error> {
+    let path = file.with_extension("").iter()
+        .map(|component| component.to_string_lossy().into_owned())
+        .collect::<Vec<String>>()
+        .join("\\");
+    TagPath::new(&path, group).map_err(|_| Error::TagIo { tag: path.clone(), message: format!("Not a valid tag path: {}", file.display()) })
+}
+
+fn is_same_dir(a: &Path, b: &Path) -> bool {
+    match (fs::canonicalize(a), fs::canonicalize(b)) {
+        (Ok(a), Ok(b)) => a == b,
+        _ => a == b,
+    }
+}
@@ -0,0 +1,54 @@
+//! Renders Halo CE slayer spawn randoms into a BSP's lightmap and places markers at spawns.
+//!
+//! The `spawn-camp` binary is a thin command line interface over this crate. The [`run`] module has the same
+//! operations as its commands, working on tags directories and recording every change in a
+//! [`ChangePlan`](plan::ChangePlan). The building blocks underneath work on ringhopper tag values directly:
+//!
+//! - [`spawns`] reads player starting locations from a `Scenario`.
+//! - [`markers`] adds and removes spawn marker scenery in a `Scenario`.
+//! - [`randoms`] renders randoms for a `ScenarioStructureBSP` into a copy of its lightmap `Bitmap`, using an
+//!   [`LmRenderer`](lm_render::LmRenderer).
+//! - [`lm_bitmap`] validates lightmap bitmaps and limits how much randoms lightmaps are scaled up.
+//!
+//! Nothing is printed by the library itself. Functions which report progress take an
+//! [`EventSink`](output::EventSink), which the binary prints as text or JSON.
+
+#![warn(missing_docs)]
+
+/// The error type of every operation.
+pub mod error;
+/// Progress output, as text or JSON events.
+pub mod output;
+/// Reading player starting locations.
+pub mod spawns;
+/// Reading and writing tags.
+pub mod tags;
+/// Rendering randoms into lightmap bitmaps.
+pub mod randoms;
+/// The operations behind each command.
+pub mod run;
+/// Adding and removing spawn markers.
+pub mod markers;
+/// The Vulkan renderer.
+pub mod lm_render;
+/// Lightmap bitmaps.
+pub mod lm_bitmap;
+/// Reducing rendered pages to 16-bit color.
+pub mod dither;
+/// Moving spawn markers onto the ground.
+pub mod ground;
+/// The record of what spawn-camp added to each scenario.
+pub mod manifest;
+/// The changes a run makes, for dry runs.
+pub mod plan;
+/// Layered tags directories.
+pub mod layers;
+/// Backups of the files each run changes.
+pub mod backup;
+/// Exporting spawns for other tools.
+pub mod export;
+/// Statistics about spawn layouts.
+pub mod report;
+mod fallback_marker;
+mod migrate;
+mod clean;
@@ -1,28 +1,44 @@
-use ringhopper::definitions::{Bitmap, BitmapData, BitmapDataFlags, BitmapDataFormat, BitmapDataType, BitmapFormat, BitmapGroupSequence, BitmapType, BitmapUsage};
-use ringhopper::primitives::primitive::{Data, Reflexive, TagGroup, Vector2DInt};
+use ringhopper::definitions::{Bitmap, BitmapData, BitmapDataFormat, BitmapDataType, ScenarioStructureBSP};
+use ringhopper::primitives::primitive::{Data, Reflexive, Vector2DInt};
 use ringhopper::tag::bitmap::bits_per_pixel;
 
 #[derive(Copy, Clone)]
-pub struct Dimensions {
+pub(crate) struct Dimensions {
     pub w: u16,
     pub h: u16
 }
 
-pub struct LmPage {
+/// Limits on how much the randoms lightmap's pages are scaled up from the original's.
+pub struct LmScaling {
+    /// Scale of every page, unless the limits below need it smaller. Pages are only ever scaled by powers of two.
+    pub max_scale: u16,
+    /// If set, pages are scaled down until their width and height fit within this many pixels.
+    pub max_dimension: Option<u16>,
+    /// If set, the largest pages are scaled down until the whole tag fits within this many bytes.
+    pub max_size: Option<usize>,
+}
+
+pub(crate) struct LmPage {
     pub dimensions: Dimensions,
     pub data_format: BitmapDataFormat,
     pub data: Vec<u8>,
 }
 
-pub fn get_lm_page(bitmap: &Bitmap, index: u16) -> Result<LmPage, String> {
+pub(crate) fn get_lm_page(bitmap: &Bitmap, index: u16) -> Result<LmPage, String> {
     let bitmap_data = bitmap.bitmap_data.items.get(index as usize)
         .ok_or(format!("Bitmap does not contain data index {}", index))?;
 
-    let bytes_per_pixel = bits_per_pixel(bitmap_data.format).get() / 8;
-    let data_size = bytes_per_pixel * bitmap_data.width as usize * bitmap_data.height as usize;
+    let data_size = get_page_data_size(Dimensions { w: bitmap_data.width, h: bitmap_data.height }, bitmap_data.format);
     let data_offset_start = bitmap_data.pixel_data_offset as usize;
     let data_offset_end = data_offset_start + data_size;
-    let data: &[u8] = &bitmap.processed_pixel_data.bytes[data_offset_start..data_offset_end];
+    let data: &[u8] = bitmap.processed_pixel_data.bytes.get(data_offset_start..data_offset_end)
+        .ok_or(format!(
+            "Bitmap data index {} has pixel data range {}..{} outside of the bitmap's {} bytes of pixel data",
+            index,
+            data_offset_start,
+            data_offset_end,
+            bitmap.processed_pixel_data.bytes.len()
+        ))?;
 
     Ok(LmPage {
         dimensions: Dimensions {
@@ -34,47 +50,301 @@ pub fn get_lm_page(bitmap: &Bitmap, index: u16) -> Result<LmPage, String> {
     })
 }
 
-pub fn create_lm_bitmap(pages: &[LmPage]) -> Bitmap {
+/// Checks everything about the original lightmap bitmap which rendering relies on, returning a description of each problem.
+pub fn validate_lm_bitmap(bitmap: &Bitmap, bsp: &ScenarioStructureBSP) -> Vec<String> {
+    let mut problems: Vec<String> = Vec::new();
+    let page_count = bitmap.bitmap_data.items.len();
+    let pixel_data_size = bitmap.processed_pixel_data.bytes.len();
+
+    if page_count == 0 {
+        problems.push("Bitmap has no bitmap data".into());
+    }
+
+    for (i, bitmap_data) in bitmap.bitmap_data.items.iter().enumerate() {
+        if !is_supported_lm_format(bitmap_data.format) {
+            problems.push(format!("Bitmap data index {} has unsupported format {:?}, expected R5G6B5", i, bitmap_data.format));
+        }
+        if bitmap_data._type != BitmapDataType::_2dTexture || bitmap_data.depth != 1 {
+            problems.push(format!("Bitmap data index {} is not a 2D texture", i));
+        }
+        if bitmap_data.width == 0 || bitmap_data.height == 0 {
+            problems.push(format!("Bitmap data index {} has empty dimensions {}x{}", i, bitmap_data.width, bitmap_data.height));
+            continue;
+        }
+        let power_of_two = bitmap_data.width.is_power_of_two() && bitmap_data.height.is_power_of_two();
+        if power_of_two != bitmap_data.flags.power_of_two_dimensions {
+            problems.push(format!("Bitmap data index {} has dimensions {}x{} which do not match its power of two flag", i, bitmap_data.width, bitmap_data.height));
+        }
+        let data_size = get_page_data_size(Dimensions { w: bitmap_data.width, h: bitmap_data.height }, bitmap_data.format);
+        let data_offset_start = bitmap_data.pixel_data_offset as usize;
+        let data_offset_end = data_offset_start + data_size;
+        if data_offset_end > pixel_data_size {
+            problems.push(format!(
+                "Bitmap data index {} has pixel data range {}..{} outside of the bitmap's {} bytes of pixel data",
+                i,
+                data_offset_start,
+                data_offset_end,
+                pixel_data_size
+            ));
+        }
+    }
+
+    for (i, sequence) in bitmap.bitmap_group_sequence.items.iter().enumerate() {
+        if let Some(first_bitmap_index) = sequence.first_bitmap_index {
+            let sequence_end = first_bitmap_index as usize + sequence.bitmap_count as usize;
+            if sequence_end > page_count {
+                problems.push(format!("Sequence {} references bitmap data {}..{} but the bitmap only has {}", i, first_bitmap_index, sequence_end, page_count));
+            }
+        }
+    }
+
+    let mut referenced_pages: Vec<u16> = Vec::new();
+    for (i, bsp_lightmap) in bsp.lightmaps.items.iter().enumerate() {
+        if let Some(lm_bitmap_index) = bsp_lightmap.bitmap {
+            if lm_bitmap_index as usize >= page_count {
+                problems.push(format!("BSP lightmap {} references bitmap data index {} but the bitmap only has {}", i, lm_bitmap_index, page_count));
+            } else if referenced_pages.contains(&lm_bitmap_index) {
+                problems.push(format!("BSP lightmap {} references bitmap data index {} which is already used by another lightmap", i, lm_bitmap_index));
+            }
+            referenced_pages.push(lm_bitmap_index);
+        }
+    }
+
+    problems
+}
+
+pub(crate) fn is_supported_lm_format(format: BitmapDataFormat) -> bool {
+    format == BitmapDataFormat::R5G6B5
+}
+
+/// Pages are given by bitmap data index; any index without a rendered page keeps the original's page.
+pub(crate) fn create_lm_bitmap(original: &Bitmap, pages: &[Option<LmPage>]) -> Result<Bitmap, String> {
     let mut pixel_data: Vec<u8> = Vec::new();
-    pages.iter().for_each(|page| {
+    let mut bitmap_data: Vec<BitmapData> = Vec::new();
+
+    for (lm_bitmap_index, original_data) in original.bitmap_data.items.iter().enumerate() {
+        let original_page;
+        let page = match pages.get(lm_bitmap_index) {
+            Some(Some(page)) => page,
+            _ => {
+                original_page = get_lm_page(original, lm_bitmap_index as u16)?;
+                &original_page
+            }
+        };
+
+        let dimensions = page.dimensions;
+        //everything else about the page, like its flags, is kept from the original
+        bitmap_data.push(BitmapData {
+            width: dimensions.w,
+            height: dimensions.h,
+            format: page.data_format,
+            //keep the registration point in the same relative position for scaled pages
+            registration_point: Vector2DInt {
+                x: (original_data.registration_point.x as i32 * dimensions.w as i32 / original_data.width.max(1) as i32) as i16,
+                y: (original_data.registration_point.y as i32 * dimensions.h as i32 / original_data.height.max(1) as i32) as i16,
+            },
+            //only the base level of each page is kept
+            mipmap_count: 0,
+            pixel_data_offset: pixel_data.len() as u32,
+            ..original_data.clone()
+        });
         pixel_data.extend(&page.data);
-    });
-    Bitmap {
-        _type: BitmapType::_2dTextures,
-        encoding_format: BitmapFormat::_16Bit,
-        usage: BitmapUsage::LightMap,
+    }
+
+    Ok(Bitmap {
         processed_pixel_data: Data::new(pixel_data),
-        bitmap_group_sequence: Reflexive::new((0..pages.len()).map(|i| {
-            BitmapGroupSequence {
-                bitmap_count: 1,
-                first_bitmap_index: Some(i as u16),
-                ..BitmapGroupSequence::default()
+        bitmap_data: Reflexive::new(bitmap_data),
+        ..original.clone()
+    })
+}
+
+//sizes of everything in a bitmap tag file besides its pixel data. lightmaps have no color plate or sprites. the header is
+//the 64 byte header every HEK tag file starts with; the rest are the sizes of the bitmap (0x6C), bitmap_group_sequence
+//(0x40) and bitmap_data (0x30) structs in the tag definitions ringhopper is generated from
+const TAG_HEADER_SIZE: usize = 64;
+const BITMAP_STRUCT_SIZE: usize = 108;
+const BITMAP_SEQUENCE_SIZE: usize = 64;
+const BITMAP_DATA_SIZE: usize = 48;
+
+/// The size of the bitmap's tag file without its pixel data: the tag header, sequences and bitmap data entries.
+pub(crate) fn get_tag_overhead_size(bitmap: &Bitmap) -> usize {
+    TAG_HEADER_SIZE
+        + BITMAP_STRUCT_SIZE
+        + bitmap.bitmap_group_sequence.items.len() * BITMAP_SEQUENCE_SIZE
+        + bitmap.bitmap_data.items.len() * BITMAP_DATA_SIZE
+}
+
+/// The size of the bitmap's tag file.
+pub fn get_tag_size(bitmap: &Bitmap) -> usize {
+    get_tag_overhead_size(bitmap) + bitmap.processed_pixel_data.bytes.len()
+}
+
+pub(crate) fn get_page_data_size(dimensions: Dimensions, format: BitmapDataFormat) -> usize {
+    bits_per_pixel(format).get() * dimensions.w as usize * dimensions.h as usize / 8
+}
+
+/// Picks the largest power-of-two scale for each page which fits within the dimension and size limits. The fixed size
+/// is everything in the tag besides the pages being scaled.
+pub(crate) fn choose_page_scales(original_dimensions: &[Dimensions], output_format: BitmapDataFormat, fixed_size: usize, scaling: &LmScaling) -> Result<Vec<u16>, String> {
+    let scaled_size = |dimensions: Dimensions, scale: u16| {
+        get_page_data_size(Dimensions { w: dimensions.w * scale, h: dimensions.h * scale }, output_format)
+    };
+
+    let mut scales: Vec<u16> = original_dimensions.iter().enumerate().map(|(i, dimensions)| {
+        let max_dimension = scaling.max_dimension.unwrap_or(u16::MAX) as u32;
+        let mut scale = scaling.max_scale;
+        while scale > 1 && (dimensions.w as u32 * scale as u32 > max_dimension || dimensions.h as u32 * scale as u32 > max_dimension) {
+            scale /= 2;
+        }
+        if dimensions.w as u32 * scale as u32 > max_dimension || dimensions.h as u32 * scale as u32 > max_dimension {
+            return Err(format!("Lightmap page {} is {}x{} which already exceeds the maximum dimension {}", i, dimensions.w, dimensions.h, max_dimension));
+        }
+        Ok(scale)
+    }).collect::<Result<_, String>>()?;
+
+    if let Some(max_size) = scaling.max_size {
+        //shrink the page currently taking the most space until the total fits
+        loop {
+            let total_size = fixed_size + original_dimensions.iter().zip(scales.iter())
+                .map(|(dimensions, scale)| scaled_size(*dimensions, *scale))
+                .sum::<usize>();
+            if total_size <= max_size {
+                break;
+            }
+            let largest = original_dimensions.iter().zip(scales.iter()).enumerate()
+                .filter(|(_, (_, scale))| **scale > 1)
+                .max_by_key(|(_, (dimensions, scale))| scaled_size(**dimensions, **scale))
+                .map(|(i, _)| i);
+            match largest {
+                Some(i) => scales[i] /= 2,
+                None => return Err(format!(
+                    "The randoms lightmap tag needs {:.2} MiB even at scale 1, which exceeds the maximum size of {:.2} MiB",
+                    to_mib(total_size),
+                    to_mib(max_size)
+                )),
             }
-        }).collect()),
-        bitmap_data: Reflexive::new(pages.iter().enumerate().map(|(lm_bitmap_index, page)| {
-            let dimensions = page.dimensions;
+        }
+    }
+
+    Ok(scales)
+}
+
+pub(crate) fn to_mib(bytes: usize) -> f64 {
+    bytes as f64 / (1024.0 * 1024.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ringhopper::definitions::{BitmapDataFlags, BitmapGroupSequence, ScenarioStructureBSPLightmap};
+
+    //a BSP with a lightmap using each of the given pages
+    fn bsp(lm_bitmap_indices: &[u16]) -> ScenarioStructureBSP {
+        let lightmaps = lm_bitmap_indices.iter().map(|i| ScenarioStructureBSPLightmap { bitmap: Some(*i), ..Default::default() }).collect();
+        ScenarioStructureBSP { lightmaps: Reflexive::new(lightmaps), ..Default::default() }
+    }
+
+    #[test]
+    fn valid_bitmap_has_no_problems() {
+        assert_eq!(validate_lm_bitmap(&original_bitmap(), &bsp(&[0, 1, 2])), Vec::<String>::new());
+    }
+
+    #[test]
+    fn wrong_format_is_a_problem() {
+        let mut bitmap = original_bitmap();
+        bitmap.bitmap_data.items[1].format = BitmapDataFormat::A8R8G8B8;
+        let problems = validate_lm_bitmap(&bitmap, &bsp(&[0, 1, 2]));
+        assert!(problems.iter().any(|problem| problem.contains("index 1 has unsupported format")), "{:?}", problems);
+    }
+
+    #[test]
+    fn bsp_referencing_missing_page_is_a_problem() {
+        let problems = validate_lm_bitmap(&original_bitmap(), &bsp(&[0, 1, 3]));
+        assert_eq!(problems, vec!["BSP lightmap 2 references bitmap data index 3 but the bitmap only has 3".to_string()]);
+    }
+
+    fn scaling(max_scale: u16, max_dimension: Option<u16>, max_size: Option<usize>) -> LmScaling {
+        LmScaling { max_scale, max_dimension, max_size }
+    }
+
+    //pages of different sizes so their order shows in the output
+    fn original_bitmap() -> Bitmap {
+        let sizes = [(4, 4), (8, 2), (2, 8)];
+        let mut pixel_data: Vec<u8> = Vec::new();
+        let bitmap_data = sizes.iter().enumerate().map(|(i, &(w, h))| {
+            let offset = pixel_data.len() as u32;
+            pixel_data.extend(vec![i as u8; w as usize * h as usize * 2]);
             BitmapData {
-                signature: TagGroup::Bitmap,
-                width: dimensions.w,
-                height: dimensions.h,
+                width: w,
+                height: h,
                 depth: 1,
-                _type: BitmapDataType::_2dTexture,
-                format: page.data_format,
-                flags: BitmapDataFlags {
-                    power_of_two_dimensions: true,
-                    ..BitmapDataFlags::default()
-                },
-                registration_point: Vector2DInt {
-                    x: (dimensions.w / 2) as i16,
-                    y: (dimensions.h / 2) as i16,
-                },
-                mipmap_count: 0,
-                pixel_data_offset: (0..lm_bitmap_index)
-                    .map(|i| pages[i].data.len() as u32)
-                    .sum(),
-                ..BitmapData::default()
+                format: BitmapDataFormat::R5G6B5,
+                flags: BitmapDataFlags { power_of_two_dimensions: true, ..Default::default() },
+                registration_point: Vector2DInt { x: w as i16 / 2, y: h as i16 / 2 },
+                mipmap_count: 2,
+                pixel_data_offset: offset,
+                ..Default::default()
             }
-        }).collect()),
-        ..Bitmap::default()
+        }).collect();
+        Bitmap {
+            bitmap_group_sequence: Reflexive::new(vec![BitmapGroupSequence { bitmap_count: 3, first_bitmap_index: Some(0), ..Default::default() }]),
+            bitmap_data: Reflexive::new(bitmap_data),
+            processed_pixel_data: Data::new(pixel_data),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn pages_keep_their_indices_and_metadata() {
+        let original = original_bitmap();
+        //only the middle page is rendered, at twice the size
+        let rendered = LmPage { dimensions: Dimensions { w: 16, h: 4 }, data_format: BitmapDataFormat::R5G6B5, data: vec![9; 16 * 4 * 2] };
+        let bitmap = create_lm_bitmap(&original, &[None, Some(rendered), None]).unwrap();
+
+        let dimensions: Vec<(u16, u16)> = bitmap.bitmap_data.items.iter().map(|data| (data.width, data.height)).collect();
+        assert_eq!(dimensions, vec![(4, 4), (16, 4), (2, 8)]);
+        assert_eq!(bitmap.bitmap_group_sequence, original.bitmap_group_sequence);
+
+        for (data, original_data) in bitmap.bitmap_data.items.iter().zip(original.bitmap_data.items.iter()) {
+            assert_eq!(data.format, original_data.format);
+            assert_eq!(data.flags, original_data.flags);
+            assert_eq!(data.mipmap_count, 0);
+        }
+        assert_eq!(bitmap.bitmap_data.items[0].registration_point, Vector2DInt { x: 2, y: 2 });
+        assert_eq!(bitmap.bitmap_data.items[1].registration_point, Vector2DInt { x: 8, y: 2 });
+
+        //unrendered pages are copied from the original, and every page's data follows the one before it
+        for (i, fill) in [0u8, 9, 2].into_iter().enumerate() {
+            let page = get_lm_page(&bitmap, i as u16).unwrap();
+            assert!(page.data.iter().all(|byte| *byte == fill));
+        }
+        assert_eq!(bitmap.processed_pixel_data.bytes.len(), (16 + 64 + 16) * 2);
+    }
+
+    #[test]
+    fn scales_are_halved_to_fit_max_dimension() {
+        let pages = [Dimensions { w: 128, h: 64 }, Dimensions { w: 32, h: 32 }];
+        let scales = choose_page_scales(&pages, BitmapDataFormat::R5G6B5, 0, &scaling(8, Some(512), None)).unwrap();
+        assert_eq!(scales, vec![4, 8]);
+    }
+
+    #[test]
+    fn page_already_over_max_dimension_is_an_error() {
+        let pages = [Dimensions { w: 1024, h: 64 }];
+        assert!(choose_page_scales(&pages, BitmapDataFormat::R5G6B5, 0, &scaling(2, Some(512), None)).is_err());
+    }
+
+    #[test]
+    fn largest_page_shrinks_first_to_fit_max_size() {
+        //at scale 4 these are 512 KiB and 128 KiB as R5G6B5
+        let pages = [Dimensions { w: 128, h: 128 }, Dimensions { w: 64, h: 64 }];
+        let scales = choose_page_scales(&pages, BitmapDataFormat::R5G6B5, 1000, &scaling(4, None, Some(301_000))).unwrap();
+        assert_eq!(scales, vec![2, 4]);
+    }
+
+    #[test]
+    fn too_large_at_scale_1_is_an_error() {
+        let pages = [Dimensions { w: 128, h: 128 }];
+        assert!(choose_page_scales(&pages, BitmapDataFormat::R5G6B5, 1000, &scaling(4, None, Some(32_768))).is_err());
     }
 }
@@ -1,3 +1,4 @@
+use std::fmt::Debug;
 use std::sync::Arc;
 use hex_color::HexColor;
 use ringhopper::definitions::BitmapDataFormat;
@@ -28,11 +29,16 @@ use vulkano::pipeline::graphics::multisample::MultisampleState;
 use vulkano::pipeline::graphics::rasterization::{PolygonMode, RasterizationState};
 use vulkano::pipeline::layout::{PipelineDescriptorSetLayoutCreateInfo};
 use vulkano::shader::EntryPoint;
+use crate::error::Error;
 use crate::lm_bitmap::{Dimensions, LmPage};
-use crate::SpawnInfo;
+use crate::spawns::SpawnInfo;
 
+/// How the randoms color is blended over the original lightmap.
+#[derive(Copy, Clone)]
 pub enum BlendMode {
+    /// Mixes the randoms color over the lightmap by its alpha.
     Normal = 0,
+    /// Multiplies the lightmap by the randoms color, keeping its shading.
     Multiply = 1,
 }
 
@@ -54,7 +60,7 @@ struct UniformData {
 
 #[derive(BufferContents, Vertex)]
 #[repr(C)]
-pub struct Vert {
+pub(crate) struct Vert {
     #[format(R32G32_SFLOAT)]
     pub lm_uv: [f32; 2],
 
@@ -65,6 +71,7 @@ pub struct Vert {
     pub world_normal: [f32; 3],
 }
 
+/// Renders randoms into lightmap pages with Vulkan.
 pub struct LmRenderer {
     device: Arc<Device>,
     queue: Arc<Queue>,
@@ -79,21 +86,24 @@ pub struct LmRenderer {
     page_sampler: Arc<Sampler>,
 }
 
-const OUTPUT_BYTES_PER_PIXEL: usize = 2; //16 bit
-const OUTPUT_IMAGE_FORMAT: Format = Format::R5G6B5_UNORM_PACK16;
-const OUTPUT_BITMAP_DATA_FORMAT: BitmapDataFormat = BitmapDataFormat::R5G6B5;
+//randoms are rendered at 32 bit and quantized afterwards so they can be dithered
+const RENDER_BYTES_PER_PIXEL: usize = 4;
+const RENDER_IMAGE_FORMAT: Format = Format::B8G8R8A8_UNORM;
+const RENDER_BITMAP_DATA_FORMAT: BitmapDataFormat = BitmapDataFormat::A8R8G8B8;
+pub(crate) const OUTPUT_BITMAP_DATA_FORMAT: BitmapDataFormat = BitmapDataFormat::R5G6B5;
 
 impl LmRenderer {
-    pub fn init(spawns: &[SpawnInfo], randoms_color: HexColor, blend_mode: BlendMode, walkable_only: bool) -> LmRenderer {
-        let library = VulkanLibrary::new().expect("No Vulkan library present");
+    /// Sets up Vulkan. Spawns and style are set separately so one renderer can be shared between scenarios.
+    pub fn init() -> Result<LmRenderer, Error> {
+        let library = VulkanLibrary::new().map_err(renderer_err("No Vulkan library present"))?;
         let instance = Instance::new(library, InstanceCreateInfo {
             flags: InstanceCreateFlags::ENUMERATE_PORTABILITY,
             ..Default::default()
-        }).expect("Failed to create vulkan instance");
+        }).map_err(renderer_err("Failed to create vulkan instance"))?;
 
         let physical_device = instance
             .enumerate_physical_devices()
-            .expect("Could not enumerate vulkan devices")
+            .map_err(renderer_err("Could not enumerate vulkan devices"))?
             .min_by_key(|d| match d.properties().device_type {
                 PhysicalDeviceType::DiscreteGpu => 0,
                 PhysicalDeviceType::IntegratedGpu => 1,
@@ -102,7 +112,7 @@ impl LmRenderer {
                 PhysicalDeviceType::Other => 4,
                 _ => 5,
             })
-            .expect("No vulkan devices available");
+            .ok_or_else(|| renderer_error("No vulkan devices available"))?;
 
         let queue_family_index = physical_device
             .queue_family_properties()
@@ -110,7 +120,7 @@ impl LmRenderer {
             .position(|queue_family_properties| {
                 queue_family_properties.queue_flags.contains(QueueFlags::GRAPHICS)
             })
-            .expect("Could not find a device queue family supporting graphics") as u32;
+            .ok_or_else(|| renderer_error("Could not find a device queue family supporting graphics"))? as u32;
 
         let (device, mut queues) = Device::new(physical_device, DeviceCreateInfo {
             queue_create_infos: vec![QueueCreateInfo {
@@ -118,32 +128,32 @@ impl LmRenderer {
                 ..Default::default()
             }],
             ..Default::default()
-        }).expect("Failed to create vulkan device");
-        let queue = queues.next().unwrap();
+        }).map_err(renderer_err("Failed to create vulkan device"))?;
+        let queue = queues.next().ok_or_else(|| renderer_error("The vulkan device has no queues"))?;
 
         let memory_allocator = Arc::new(StandardMemoryAllocator::new_default(device.clone()));
         let command_buffer_allocator = Arc::new(StandardCommandBufferAllocator::new(device.clone(), StandardCommandBufferAllocatorCreateInfo::default()));
         let descriptor_set_allocator = Arc::new(StandardDescriptorSetAllocator::new(device.clone(), StandardDescriptorSetAllocatorCreateInfo::default()));
 
         let uniform_buffer = create_buffer(
-            create_uniform_data(spawns, randoms_color, blend_mode, walkable_only),
+            create_uniform_data(),
             BufferUsage::UNIFORM_BUFFER,
             MemoryTypeFilter::HOST_SEQUENTIAL_WRITE | MemoryTypeFilter::PREFER_DEVICE,
             memory_allocator.clone()
-        );
+        )?;
 
         let page_sampler = Sampler::new(device.clone(), SamplerCreateInfo {
             mag_filter: Filter::Linear,
             min_filter: Filter::Linear,
             address_mode: [SamplerAddressMode::ClampToEdge; 3],
             ..Default::default()
-        }).unwrap();
+        }).map_err(renderer_err("Failed to create lightmap sampler"))?;
 
         let render_pass = vulkano::single_pass_renderpass!(
             device.clone(),
             attachments: {
                 color: {
-                    format: OUTPUT_IMAGE_FORMAT,
+                    format: RENDER_IMAGE_FORMAT,
                     samples: 1,
                     load_op: Load,
                     store_op: Store,
@@ -153,13 +163,13 @@ impl LmRenderer {
                 color: [color],
                 depth_stencil: {},
             }
-        ).unwrap();
+        ).map_err(renderer_err("Failed to create render pass"))?;
 
-        let (vs, fs) = load_shaders(device.clone());
+        let (vs, fs) = load_shaders(device.clone())?;
 
         let vertex_input_state = Vert::per_vertex()
             .definition(&vs.info().input_interface)
-            .unwrap();
+            .map_err(renderer_err("Failed to create vertex input state"))?;
 
         let stages = [
             PipelineShaderStageCreateInfo::new(vs),
@@ -168,18 +178,23 @@ impl LmRenderer {
 
         let layout = {
             let mut layout_create_info = PipelineDescriptorSetLayoutCreateInfo::from_stages(&stages);
-            layout_create_info.set_layouts[0].bindings.get_mut(&0).unwrap().descriptor_type = DescriptorType::UniformBuffer;
-            layout_create_info.set_layouts[0].bindings.get_mut(&1).unwrap().descriptor_type = DescriptorType::Sampler;
-            layout_create_info.set_layouts[0].bindings.get_mut(&2).unwrap().descriptor_type = DescriptorType::SampledImage;
+            let bindings = &mut layout_create_info.set_layouts.get_mut(0)
+                .ok_or_else(|| renderer_error("The shaders have no descriptor set"))?
+                .bindings;
+            for (binding, descriptor_type) in [(0u32, DescriptorType::UniformBuffer), (1, DescriptorType::Sampler), (2, DescriptorType::SampledImage)] {
+                bindings.get_mut(&binding)
+                    .ok_or_else(|| renderer_error(&format!("The shaders have no descriptor binding {}", binding)))?
+                    .descriptor_type = descriptor_type;
+            }
             PipelineLayout::new(
                 device.clone(),
                 layout_create_info
                     .into_pipeline_layout_create_info(device.clone())
-                    .unwrap()
-            ).unwrap()
+                    .map_err(renderer_err("Failed to create pipeline layout"))?
+            ).map_err(renderer_err("Failed to create pipeline layout"))?
         };
 
-        let subpass = Subpass::from(render_pass.clone(), 0).unwrap();
+        let subpass = Subpass::from(render_pass.clone(), 0).ok_or_else(|| renderer_error("The render pass has no subpass"))?;
 
         let pipeline = GraphicsPipeline::new(device.clone(), None, GraphicsPipelineCreateInfo {
             stages: stages.into_iter().collect(),
@@ -207,9 +222,9 @@ impl LmRenderer {
             )),
             subpass: Some(subpass.into()),
             ..GraphicsPipelineCreateInfo::layout(layout)
-        }).expect("Failed to create graphics pipeline");
+        }).map_err(renderer_err("Failed to create graphics pipeline"))?;
 
-        LmRenderer {
+        Ok(LmRenderer {
             device,
             queue,
             render_pass,
@@ -219,10 +234,25 @@ impl LmRenderer {
             descriptor_set_allocator,
             uniform_buffer,
             page_sampler,
-        }
+        })
     }
 
-    pub fn render_randoms(&self, lm_verts: Vec<Vert>, lm_indices: Vec<u16>, dimensions: Dimensions, original_lm_page: &LmPage) -> LmPage {
+    /// Scenarios can have their own color, blend mode and walkable setting in spawn-camp.toml.
+    pub(crate) fn set_style(&self, randoms_color: HexColor, blend_mode: BlendMode, walkable_only: bool) -> Result<(), Error> {
+        let mut data = self.uniform_buffer.write().map_err(renderer_err("Failed to write style to the uniform buffer"))?;
+        set_uniform_style(&mut data, randoms_color, blend_mode, walkable_only);
+        Ok(())
+    }
+
+    /// Sets the spawns randoms are rendered for, up to the 256 a scenario can have.
+    pub(crate) fn set_spawns(&self, spawns: &[SpawnInfo]) -> Result<(), Error> {
+        let mut data = self.uniform_buffer.write().map_err(renderer_err("Failed to write spawns to the uniform buffer"))?;
+        set_uniform_spawns(&mut data, spawns);
+        Ok(())
+    }
+
+    /// Renders randoms over a page of the original lightmap, given the page's BSP surfaces.
+    pub(crate) fn render_randoms(&self, lm_verts: Vec<Vert>, lm_indices: Vec<u16>, dimensions: Dimensions, original_lm_page: &LmPage) -> Result<LmPage, Error> {
         let num_lm_indices = lm_indices.len() as u32;
 
         let vertex_buffer = create_buffer_iter(
@@ -230,33 +260,33 @@ impl LmRenderer {
             BufferUsage::VERTEX_BUFFER,
             MemoryTypeFilter::HOST_SEQUENTIAL_WRITE | MemoryTypeFilter::PREFER_DEVICE,
             self.memory_allocator.clone()
-        );
+        )?;
         let index_buffer = create_buffer_iter(
             lm_indices,
             BufferUsage::INDEX_BUFFER,
             MemoryTypeFilter::HOST_SEQUENTIAL_WRITE | MemoryTypeFilter::PREFER_DEVICE,
             self.memory_allocator.clone()
-        );
+        )?;
         let output_buffer = create_buffer_iter(
-            vec![0u8; dimensions.w as usize * dimensions.h as usize * OUTPUT_BYTES_PER_PIXEL],
+            vec![0u8; dimensions.w as usize * dimensions.h as usize * RENDER_BYTES_PER_PIXEL],
             BufferUsage::TRANSFER_DST,
             MemoryTypeFilter::HOST_RANDOM_ACCESS | MemoryTypeFilter::PREFER_HOST,
             self.memory_allocator.clone()
-        );
+        )?;
         let page_upload_buffer = create_buffer_iter(
             original_lm_page.data.clone(),
             BufferUsage::TRANSFER_SRC,
             MemoryTypeFilter::HOST_SEQUENTIAL_WRITE | MemoryTypeFilter::PREFER_HOST,
             self.memory_allocator.clone()
-        );
-        let page_image = create_page_img(self.memory_allocator.clone(), original_lm_page);
-        let page_view = ImageView::new_default(page_image.clone()).unwrap();
+        )?;
+        let page_image = create_page_img(self.memory_allocator.clone(), original_lm_page)?;
+        let page_view = ImageView::new_default(page_image.clone()).map_err(renderer_err("Failed to create lightmap page view"))?;
 
         let output_image = Image::new(
             self.memory_allocator.clone(),
             ImageCreateInfo {
                 image_type: ImageType::Dim2d,
-                format: OUTPUT_IMAGE_FORMAT,
+                format: RENDER_IMAGE_FORMAT,
                 extent: [dimensions.w as u32, dimensions.h as u32, 1],
                 usage: ImageUsage::COLOR_ATTACHMENT | ImageUsage::TRANSFER_SRC | ImageUsage::TRANSFER_DST,
                 ..Default::default()
@@ -265,13 +295,13 @@ impl LmRenderer {
                 memory_type_filter: MemoryTypeFilter::PREFER_DEVICE,
                 ..Default::default()
             },
-        ).unwrap();
+        ).map_err(renderer_err("Failed to create output image"))?;
 
-        let view = ImageView::new_default(output_image.clone()).unwrap();
+        let view = ImageView::new_default(output_image.clone()).map_err(renderer_err("Failed to create output image view"))?;
         let framebuffer = Framebuffer::new(self.render_pass.clone(), FramebufferCreateInfo {
             attachments: vec![view],
             ..Default::default()
-        }).unwrap();
+        }).map_err(renderer_err("Failed to create framebuffer"))?;
 
         let dynamic_viewport = Viewport {
             offset: [0.0, 0.0],
@@ -279,7 +309,7 @@ impl LmRenderer {
             depth_range: 0.0..=1.0,
         };
 
-        let descriptor_set_layout = self.pipeline.layout().set_layouts().get(0).unwrap();
+        let descriptor_set_layout = self.pipeline.layout().set_layouts().first().ok_or_else(|| renderer_error("The pipeline has no descriptor set layout"))?;
         let descriptor_set = PersistentDescriptorSet::new(
             &self.descriptor_set_allocator,
             descriptor_set_layout.clone(),
@@ -289,22 +319,22 @@ impl LmRenderer {
                 WriteDescriptorSet::image_view(2, page_view),
             ],
             []
-        ).unwrap();
+        ).map_err(renderer_err("Failed to create descriptor set"))?;
 
         let mut command_buffer_builder = AutoCommandBufferBuilder::primary(
             &self.command_buffer_allocator,
             self.queue.queue_family_index(),
             CommandBufferUsage::OneTimeSubmit,
-        ).unwrap();
+        ).map_err(renderer_err("Failed to create command buffer"))?;
 
         let mut blit = BlitImageInfo::images(page_image.clone(), output_image.clone());
         blit.filter = Filter::Linear;
 
         command_buffer_builder
             .copy_buffer_to_image(CopyBufferToImageInfo::buffer_image(page_upload_buffer, page_image.clone()))
-            .unwrap()
+            .map_err(renderer_err("Failed to record render commands"))?
             .blit_image(blit)
-            .unwrap()
+            .map_err(renderer_err("Failed to record render commands"))?
             .begin_render_pass(
                 RenderPassBeginInfo {
                     clear_values: vec![None],
@@ -315,75 +345,85 @@ impl LmRenderer {
                     ..Default::default()
                 }
             )
-            .unwrap()
+            .map_err(renderer_err("Failed to record render commands"))?
             .bind_pipeline_graphics(self.pipeline.clone())
-            .unwrap()
+            .map_err(renderer_err("Failed to record render commands"))?
             .set_viewport(0, [dynamic_viewport].into_iter().collect())
-            .unwrap()
+            .map_err(renderer_err("Failed to record render commands"))?
             .bind_vertex_buffers(0, vertex_buffer.clone())
-            .unwrap()
+            .map_err(renderer_err("Failed to record render commands"))?
             .bind_index_buffer(index_buffer)
-            .unwrap()
+            .map_err(renderer_err("Failed to record render commands"))?
             .bind_descriptor_sets(
                 PipelineBindPoint::Graphics,
                 self.pipeline.layout().clone(),
                 0,
                 descriptor_set.clone()
             )
-            .unwrap()
+            .map_err(renderer_err("Failed to record render commands"))?
             .draw_indexed(num_lm_indices, 1, 0, 0, 0)
-            .unwrap()
+            .map_err(renderer_err("Failed to record render commands"))?
             .end_render_pass(SubpassEndInfo::default())
-            .unwrap()
+            .map_err(renderer_err("Failed to record render commands"))?
             .copy_image_to_buffer(CopyImageToBufferInfo::image_buffer(
                 output_image.clone(),
                 output_buffer.clone()
             ))
-            .unwrap();
+            .map_err(renderer_err("Failed to record render commands"))?;
 
-        let command_buffer = command_buffer_builder.build().unwrap();
+        let command_buffer = command_buffer_builder.build().map_err(renderer_err("Failed to build command buffer"))?;
 
         sync::now(self.device.clone())
             .then_execute(self.queue.clone(), command_buffer)
-            .unwrap()
+            .map_err(renderer_err("Failed to execute render commands"))?
             .then_signal_fence_and_flush()
-            .unwrap()
+            .map_err(renderer_err("Failed to execute render commands"))?
             .wait(None)
-            .unwrap();
+            .map_err(renderer_err("Failed to execute render commands"))?;
 
-        let result_data: Vec<u8> = output_buffer.read().unwrap().iter().cloned().collect();
-        LmPage {
+        let result_data: Vec<u8> = output_buffer.read().map_err(renderer_err("Failed to read the rendered page"))?.iter().cloned().collect();
+        Ok(LmPage {
             data: result_data,
-            data_format: OUTPUT_BITMAP_DATA_FORMAT,
+            data_format: RENDER_BITMAP_DATA_FORMAT,
             dimensions: dimensions.clone(),
-        }
+        })
     }
 }
 
-fn create_uniform_data(spawns: &[SpawnInfo], randoms_color: HexColor, blend_mode: BlendMode, walkable_only: bool) -> UniformData {
-    let mut data = UniformData {
-        spawn_count: spawns.len() as u32,
+fn create_uniform_data() -> UniformData {
+    UniformData {
+        spawn_count: 0,
         spawns: [SpawnData::default(); 256],
-        randoms_color: [
-            (randoms_color.r as f32 / 255.0),
-            (randoms_color.g as f32 / 255.0),
-            (randoms_color.b as f32 / 255.0),
-            (randoms_color.a as f32 / 255.0),
-        ],
-        blend_mode: blend_mode as u32,
-        walkable_only: if walkable_only { 1 } else { 0 }
-    };
+        randoms_color: [0.0; 4],
+        blend_mode: BlendMode::Normal as u32,
+        walkable_only: 0,
+    }
+}
+
+fn set_uniform_style(data: &mut UniformData, randoms_color: HexColor, blend_mode: BlendMode, walkable_only: bool) {
+    data.randoms_color = [
+        (randoms_color.r as f32 / 255.0),
+        (randoms_color.g as f32 / 255.0),
+        (randoms_color.b as f32 / 255.0),
+        (randoms_color.a as f32 / 255.0),
+    ];
+    data.blend_mode = blend_mode as u32;
+    data.walkable_only = if walkable_only { 1 } else { 0 };
+}
+
+fn set_uniform_spawns(data: &mut UniformData, spawns: &[SpawnInfo]) {
+    data.spawn_count = spawns.len() as u32;
+    data.spawns = [SpawnData::default(); 256];
     spawns.iter().enumerate().for_each(|(i, s)| {
         data.spawns[i].world_pos = [
-            s.position.x as f32,
-            s.position.y as f32,
-            s.position.z as f32,
+            s.position.x,
+            s.position.y,
+            s.position.z,
         ];
     });
-    data
 }
 
-fn load_shaders(device: Arc<Device>) -> (EntryPoint, EntryPoint) {
+fn load_shaders(device: Arc<Device>) -> Result<(EntryPoint, EntryPoint), Error> {
     mod vs {
         vulkano_shaders::shader! { ty: "vertex", path: "src/vert.glsl" }
     }
@@ -393,39 +433,40 @@ fn load_shaders(device: Arc<Device>) -> (EntryPoint, EntryPoint) {
     }
 
     let vs = vs::load(device.clone())
-        .expect("Failed to create vertex shader module")
+        .map_err(renderer_err("Failed to create vertex shader module"))?
         .entry_point("main")
-        .unwrap();
+        .ok_or_else(|| renderer_error("The vertex shader has no main entry point"))?;
     let fs = fs::load(device.clone())
-        .expect("Failed to create fragment shader module")
+        .map_err(renderer_err("Failed to create fragment shader module"))?
         .entry_point("main")
-        .unwrap();
+        .ok_or_else(|| renderer_error("The fragment shader has no main entry point"))?;
 
-    (vs, fs)
+    Ok((vs, fs))
 }
 
-fn create_buffer_iter<T: BufferContents>(items: Vec<T>, usage: BufferUsage, memory_type_filter: MemoryTypeFilter, allocator: Arc<dyn MemoryAllocator>) -> Subbuffer<[T]> {
+fn create_buffer_iter<T: BufferContents>(items: Vec<T>, usage: BufferUsage, memory_type_filter: MemoryTypeFilter, allocator: Arc<dyn MemoryAllocator>) -> Result<Subbuffer<[T]>, Error> {
     Buffer::from_iter(
         allocator,
         BufferCreateInfo { usage, ..Default::default() },
         AllocationCreateInfo { memory_type_filter, ..Default::default() },
         items,
-    ).expect("Failed to create buffer")
+    ).map_err(renderer_err("Failed to create buffer"))
 }
 
-fn create_buffer<T: BufferContents>(data: T, usage: BufferUsage, memory_type_filter: MemoryTypeFilter, allocator: Arc<dyn MemoryAllocator>) -> Subbuffer<T> {
+fn create_buffer<T: BufferContents>(data: T, usage: BufferUsage, memory_type_filter: MemoryTypeFilter, allocator: Arc<dyn MemoryAllocator>) -> Result<Subbuffer<T>, Error> {
     Buffer::from_data(
         allocator,
         BufferCreateInfo { usage, ..Default::default() },
         AllocationCreateInfo { memory_type_filter, ..Default::default() },
         data,
-    ).expect("Failed to create buffer")
+    ).map_err(renderer_err("Failed to create buffer"))
 }
 
-fn create_page_img(allocator: Arc<dyn MemoryAllocator>, page: &LmPage) -> Arc<Image> {
+//lightmaps are validated before rendering, so other formats are normally reported before getting here
+fn create_page_img(allocator: Arc<dyn MemoryAllocator>, page: &LmPage) -> Result<Arc<Image>, Error> {
     let format: Format = match page.data_format {
         BitmapDataFormat::R5G6B5 => Format::R5G6B5_UNORM_PACK16,
-        _ => panic!("The bitmap data format is not supported")
+        format => return Err(renderer_error(&format!("The bitmap data format {:?} is not supported", format))),
     };
     Image::new(
         allocator,
@@ -440,5 +481,14 @@ fn create_page_img(allocator: Arc<dyn MemoryAllocator>, page: &LmPage) -> Arc<Im
             memory_type_filter: MemoryTypeFilter::PREFER_DEVICE,
             ..Default::default()
         }
-    ).expect("Failed to create image")
+    ).map_err(renderer_err("Failed to create image"))
+}
+
+fn renderer_error(message: &str) -> Error {
+    Error::Renderer { page: None, message: message.to_owned() }
+}
+
+//vulkano's errors are only consistently Debug, which includes the failed validation or Vulkan result
+fn renderer_err<E: Debug>(context: &'static str) -> impl FnOnce(E) -> Error {
+    move |err| Error::Renderer { page: None, message: format!("{}: {:?}", context, err) }
 }
\ No newline at end of file
@@ -0,0 +1,163 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use serde::{Deserialize, Serialize};
+use ringhopper::primitives::primitive::TagPath;
+use crate::error::Error;
+use crate::layers::find_tag_files;
+use crate::plan::{ChangePlan, ManifestChange};
+use crate::output::{Event, EventSink};
+
+/// Record of everything spawn-camp added to a scenario, kept next to the scenario tag so reset can undo exactly that.
+#[derive(Serialize, Deserialize, Default, Clone)]
+pub struct Manifest {
+    /// Scenery palette entries which didn't exist before markers were placed.
+    pub scenery_palette: Vec<String>,
+    /// Each marker placed.
+    pub markers: Vec<MarkerRecord>,
+    /// The BSP's lightmap reference, if it was replaced with a randoms bitmap.
+    pub lightmap: Option<LightmapRecord>,
+}
+
+/// A spawn marker placed in the scenario.
+#[derive(Serialize, Deserialize, Clone)]
+pub struct MarkerRecord {
+    /// Index in the scenario's scenery when it was placed.
+    pub scenery_index: usize,
+    /// Tag path of the marker scenery.
+    pub marker: String,
+    /// World position it was placed at.
+    pub position: [f32; 3],
+    /// Object name it was given, if any.
+    pub name: Option<String>,
+}
+
+/// A BSP lightmap reference replaced with a randoms bitmap.
+#[derive(Serialize, Deserialize, Clone)]
+pub struct LightmapRecord {
+    /// Tag path of the BSP.
+    pub bsp: String,
+    /// Tag path of the bitmap the BSP referenced before.
+    pub original: String,
+    /// Tag path of the randoms bitmap.
+    pub randoms: String,
+}
+
+const MANIFEST_EXTENSION: &str = ".spawn-camp.json";
+
+/// Where a scenario's manifest is kept: levels\test\chillout\chillout is recorded in
+/// tags\levels\test\chillout\chillout.spawn-camp.json.
+pub fn get_manifest_path(tags_dir: &Path, scenario_tag_path: &TagPath) -> PathBuf {
+    let native_path = format!("{}{}", scenario_tag_path.path(), MANIFEST_EXTENSION);
+    tags_dir.join(native_path.split('\\').collect::<PathBuf>())
+}
+
+/// Every manifest in the tags directory.
+pub(crate) fn find_manifests(tags_dir: &Path) -> Result<Vec<Manifest>, Error> {
+    let mut files: Vec<PathBuf> = Vec::new();
+    find_tag_files(tags_dir, tags_dir, &mut files)?;
+    files.iter()
+        .filter(|file| file.to_string_lossy().ends_with(MANIFEST_EXTENSION))
+        .filter_map(|file| read_manifest(&tags_dir.join(file)).transpose())
+        .collect()
+}
+
+/// Reads a manifest, or returns None if it doesn't exist.
+pub fn read_manifest(path: &Path) -> Result<Option<Manifest>, Error> {
+    if !path.exists() {
+        return Ok(None);
+    }
+    let contents = fs::read_to_string(path).map_err(|err| Error::io(format!("Could not read manifest {}: {}", path.display(), err), err))?;
+    serde_json::from_str(&contents)
+        .map(Some)
+        .map_err(|err| Error::io(format!("Could not parse manifest {}: {}", path.display(), err), err))
+}
+
+pub(crate) fn write_manifest(path: &Path, manifest: &Manifest, plan: &mut ChangePlan, sink: &dyn EventSink) -> Result<(), Error> {
+    plan.manifest = Some(ManifestChange::Write(path.display().to_string()));
+    if plan.dry_run {
+        sink.emit(Event::ManifestWritten { path: path.display().to_string(), dry_run: true });
+        return Ok(());
+    }
+    if let Some(backup) = &mut plan.backup {
+        backup.save_file(path, sink)?;
+    }
+    sink.emit(Event::ManifestWritten { path: path.display().to_string(), dry_run: false });
+    let contents = serde_json::to_string_pretty(manifest).map_err(|err| Error::io(format!("Could not serialize manifest: {}", err), err))?;
+    fs::write(path, contents).map_err(|err| Error::io(format!("Could not write manifest {}: {}", path.display(), err), err))
+}
+
+pub(crate) fn delete_manifest(path: &Path, plan: &mut ChangePlan, sink: &dyn EventSink) -> Result<(), Error> {
+    if path.exists() {
+        plan.manifest = Some(ManifestChange::Delete(path.display().to_string()));
+        if plan.dry_run {
+            sink.emit(Event::ManifestDeleted { path: path.display().to_string(), dry_run: true });
+            return Ok(());
+        }
+        if let Some(backup) = &mut plan.backup {
+            backup.save_file(path, sink)?;
+        }
+        sink.emit(Event::ManifestDeleted { path: path.display().to_string(), dry_run: false });
+        fs::remove_file(path).map_err(|err| Error::io(format!("Could not delete manifest {}: {}", path.display(), err), err))?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::process;
+    use super::*;
+    use crate::output::Discard;
+    use crate::layers::TagsLayers;
+
+    fn get_test_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("spawn-camp-{}-{}", name, process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn sample_manifest() -> Manifest {
+        Manifest {
+            scenery_palette: vec!["scenery\\spawn_marker\\spawn_marker".to_owned()],
+            markers: vec![MarkerRecord {
+                scenery_index: 3,
+                marker: "scenery\\spawn_marker\\spawn_marker".to_owned(),
+                position: [1.5, -2.25, 0.125],
+                name: Some("spawn_0".to_owned()),
+            }],
+            lightmap: Some(LightmapRecord {
+                bsp: "levels\\test\\chillout\\chillout".to_owned(),
+                original: "levels\\test\\chillout\\chillout".to_owned(),
+                randoms: "levels\\test\\chillout\\chillout_randoms".to_owned(),
+            }),
+        }
+    }
+
+    #[test]
+    fn written_manifest_reads_back() {
+        let dir = get_test_dir("manifest-round-trip");
+        let path = dir.join("test.spawn-camp.json");
+        let mut plan = ChangePlan::new(false, TagsLayers::new(&dir, &[]).unwrap());
+        write_manifest(&path, &sample_manifest(), &mut plan, &Discard).unwrap();
+
+        let manifest = read_manifest(&path).unwrap().unwrap();
+        assert_eq!(manifest.scenery_palette, sample_manifest().scenery_palette);
+        assert_eq!(manifest.markers.len(), 1);
+        assert_eq!(manifest.markers[0].scenery_index, 3);
+        assert_eq!(manifest.markers[0].position, [1.5, -2.25, 0.125]);
+        assert_eq!(manifest.markers[0].name.as_deref(), Some("spawn_0"));
+        assert_eq!(manifest.lightmap.map(|lightmap| lightmap.randoms).as_deref(), Some("levels\\test\\chillout\\chillout_randoms"));
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn dry_run_writes_nothing() {
+        let dir = get_test_dir("manifest-dry-run");
+        let path = dir.join("test.spawn-camp.json");
+        let mut plan = ChangePlan::new(true, TagsLayers::new(&dir, &[]).unwrap());
+        write_manifest(&path, &sample_manifest(), &mut plan, &Discard).unwrap();
+
+        assert!(read_manifest(&path).unwrap().is_none());
+        assert!(plan.manifest.is_some());
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
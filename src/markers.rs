@@ -0,0 +1,563 @@
+use ringhopper::definitions::{Scenario, ScenarioObjectName, ScenarioObjectPlacement, ScenarioScenery, ScenarioSceneryPalette, ScenarioSpawnType};
+use ringhopper::primitives::primitive::{Angle, Euler3D, Index, String32, TagPath, TagReference};
+use crate::spawns::SpawnInfo;
+use crate::error::Error;
+use crate::fallback_marker::create_fallback_marker;
+use crate::manifest::{Manifest, MarkerRecord};
+use crate::plan::ChangePlan;
+use crate::tags::{open_tag, LayeredTags};
+use crate::output::{progress, warning, Event, EventSink};
+
+/// The marker scenery placed at spawns.
+pub struct MarkerSet {
+    /// Marker scenery for spawns without a type or team override.
+    pub default: TagPath,
+    /// Overrides of the default marker by spawn type, taking precedence over teams.
+    pub types: Vec<(ScenarioSpawnType, TagPath)>,
+    /// Overrides of the default marker by spawn team index.
+    pub teams: Vec<(u16, TagPath)>,
+    /// Whether markers get object names so they can be told apart in Sapien.
+    pub named: bool,
+    /// Whether missing marker scenery is generated instead of being an error.
+    pub create_missing: bool,
+}
+
+impl MarkerSet {
+    pub(crate) fn get_marker(&self, spawn: &SpawnInfo) -> &TagPath {
+        self.get_type_marker(spawn)
+            .or_else(|| {
+                self.teams.iter()
+                    .find(|(team_index, _)| *team_index == spawn.team_index)
+                    .map(|(_, tag_path)| tag_path)
+            })
+            .unwrap_or(&self.default)
+    }
+
+    //spawns with a mapped type get a marker even if they aren't used in slayer
+    pub(crate) fn has_type_marker(&self, spawn: &SpawnInfo) -> bool {
+        self.get_type_marker(spawn).is_some()
+    }
+
+    fn get_type_marker(&self, spawn: &SpawnInfo) -> Option<&TagPath> {
+        spawn.types.iter().find_map(|spawn_type| {
+            self.types.iter()
+                .find(|(mapped_type, _)| mapped_type == spawn_type)
+                .map(|(_, tag_path)| tag_path)
+        })
+    }
+
+    pub(crate) fn all_tag_paths(&self) -> Vec<&TagPath> {
+        let mut tag_paths: Vec<&TagPath> = vec![&self.default];
+        self.types.iter().map(|(_, tag_path)| tag_path)
+            .chain(self.teams.iter().map(|(_, tag_path)| tag_path))
+            .for_each(|tag_path| {
+                if !tag_paths.contains(&tag_path) {
+                    tag_paths.push(tag_path);
+                }
+            });
+        tag_paths
+    }
+}
+
+/// Makes sure the marker scenery for each spawn exists, generating any missing ones if the marker set allows it.
+pub(crate) fn prepare_marker_tags(tags: &mut LayeredTags, spawns: &[SpawnInfo], markers: &MarkerSet, plan: &mut ChangePlan, sink: &dyn EventSink) -> Result<(), Error> {
+    for marker_tag_path in get_used_markers(spawns, markers) {
+        //only a marker which doesn't exist is generated. one which can't be read is an error rather than being
+        //overwritten, since it may just be from a newer version of the tools
+        match open_tag(&tags.all, marker_tag_path, sink) {
+            Ok(_) => (),
+            Err(Error::MissingTag { .. }) if markers.create_missing => create_fallback_marker(tags, marker_tag_path, plan, sink)?,
+            Err(Error::MissingTag { .. }) => return Err(Error::MissingTag {
+                tag: marker_tag_path.to_string(),
+                message: format!(
+                    "No marker scenery tag exists at path {}. You can get it from https://github.com/khstarr/h1-spawn-tools or use --create-marker to generate a simple one",
+                    marker_tag_path
+                ),
+            }),
+            Err(err) => return Err(err),
+        }
+    }
+    Ok(())
+}
+
+/// Adds a marker scenery placement at each spawn, facing the same way, along with scenery palette entries for the
+/// markers used. The marker scenery tags themselves aren't read. Existing markers should already be removed; everything
+/// added is recorded in the manifest.
+pub fn add_spawn_markers(scenario: &mut Scenario, spawns: &[SpawnInfo], markers: &MarkerSet, manifest: &mut Manifest, sink: &dyn EventSink) -> Result<(), Error> {
+    let mut marker_palette_indices: Vec<(&TagPath, Index)> = Vec::new();
+    for marker_tag_path in get_used_markers(spawns, markers) {
+        let marker_palette_index = match get_marker_palette(scenario, marker_tag_path) {
+            Some(index) => index,
+            None => {
+                progress!(sink, "Adding scenery palette entry {}", marker_tag_path);
+                scenario.scenery_palette.items.push(ScenarioSceneryPalette {
+                    name: TagReference::Set(marker_tag_path.clone())
+                });
+                if !manifest.scenery_palette.iter().any(|recorded| recorded == marker_tag_path.path()) {
+                    manifest.scenery_palette.push(marker_tag_path.path().to_owned());
+                }
+                Some(scenario.scenery_palette.items.len() as u16 - 1)
+            }
+        };
+        marker_palette_indices.push((marker_tag_path, marker_palette_index));
+    }
+
+    sink.emit(Event::MarkersPlaced { count: spawns.len() });
+    for spawn in spawns {
+        let marker_tag_path = markers.get_marker(spawn);
+        let marker_palette_index = marker_palette_indices.iter()
+            .find(|(tag_path, _)| *tag_path == marker_tag_path)
+            .and_then(|(_, index)| *index);
+        let name = if markers.named {
+            Some(get_unique_object_name(scenario, &get_marker_name(spawn)))
+        } else {
+            None
+        };
+        let name_index = match &name {
+            Some(name) => Some(add_object_name(scenario, name)?),
+            None => None,
+        };
+        manifest.markers.push(MarkerRecord {
+            scenery_index: scenario.scenery.items.len(),
+            marker: marker_tag_path.path().to_owned(),
+            position: [spawn.position.x, spawn.position.y, spawn.position.z],
+            name,
+        });
+        scenario.scenery.items.push(ScenarioScenery {
+            _type: marker_palette_index,
+            name: name_index,
+            placement: ScenarioObjectPlacement {
+                position: spawn.position,
+                rotation: Euler3D {
+                    yaw: spawn.facing,
+                    pitch: Angle::default(),
+                    roll: Angle::default(),
+                },
+                ..ScenarioObjectPlacement::default()
+            },
+            ..ScenarioScenery::default()
+        });
+    }
+
+    Ok(())
+}
+
+//only marker types which are actually used get a palette entry
+fn get_used_markers<'a>(spawns: &[SpawnInfo], markers: &'a MarkerSet) -> Vec<&'a TagPath> {
+    let mut used_tag_paths: Vec<&TagPath> = Vec::new();
+    spawns.iter().for_each(|spawn| {
+        let tag_path = markers.get_marker(spawn);
+        if !used_tag_paths.contains(&tag_path) {
+            used_tag_paths.push(tag_path);
+        }
+    });
+    used_tag_paths
+}
+
+/// Without a manifest markers can only be found by palette entry, so this also removes any placed by hand.
+/// Returns whether anything changed.
+pub fn remove_markers(scenario: &mut Scenario, markers: &MarkerSet, sink: &dyn EventSink) -> bool {
+    let marker_palette_indices = get_marker_palettes(scenario, &markers.all_tag_paths());
+    if marker_palette_indices.is_empty() {
+        return false;
+    }
+
+    progress!(sink, "Removing {} marker palette entries and their scenery placements", marker_palette_indices.len());
+    remove_all_markers(scenario, &marker_palette_indices, sink);
+    remove_marker_palettes(scenario, &marker_palette_indices);
+    true
+}
+
+/// Without a manifest there's no record of which markers spawn-camp placed, so only placements of the marker scenery
+/// exactly at a spawn are taken to be from an earlier run and removed. Any others may have been placed by hand and are
+/// kept. Returns how many were kept.
+pub fn remove_markers_at_spawns(scenario: &mut Scenario, spawns: &[SpawnInfo], markers: &MarkerSet, sink: &dyn EventSink) -> usize {
+    let marker_palette_indices = get_marker_palettes(scenario, &markers.all_tag_paths());
+    let (at_spawns, elsewhere): (Vec<usize>, Vec<usize>) = scenario.scenery.items.iter().enumerate()
+        .filter(|(_, scenery)| scenery._type.is_some_and(|scenery_type| marker_palette_indices.contains(&scenery_type)))
+        .map(|(i, _)| i)
+        .partition(|i| is_at_spawn(&scenario.scenery.items[*i], spawns));
+    progress!(sink, "Removing {} unrecorded markers placed at spawns", at_spawns.len());
+    remove_scenery(scenario, &at_spawns, sink);
+    elsewhere.len()
+}
+
+/// Removes only the placements and palette entries recorded in the manifest, leaving any placed by hand.
+/// Returns the recorded palette entries which are still in use by other scenery and so weren't removed.
+pub fn remove_recorded_markers(scenario: &mut Scenario, manifest: &Manifest, sink: &dyn EventSink) -> Vec<String> {
+    let mut marker_scenery_indices: Vec<usize> = Vec::new();
+    for record in &manifest.markers {
+        match find_recorded_marker(scenario, record, &marker_scenery_indices) {
+            Some(i) => marker_scenery_indices.push(i),
+            None => warning!(sink, "Recorded marker {} was already removed from the scenario", record.scenery_index),
+        }
+    }
+    progress!(sink, "Removing {} recorded markers", marker_scenery_indices.len());
+    remove_scenery(scenario, &marker_scenery_indices, sink);
+
+    let mut kept_palette_entries: Vec<String> = Vec::new();
+    let mut marker_palette_indices: Vec<u16> = Vec::new();
+    for recorded in &manifest.scenery_palette {
+        let palette_index = scenario.scenery_palette.items.iter()
+            .position(|palette_entry| palette_entry.name.path().map(|tag_path| tag_path.path() == recorded).unwrap_or(false));
+        if let Some(palette_index) = palette_index {
+            let in_use = scenario.scenery.items.iter().any(|scenery| scenery._type == Some(palette_index as u16));
+            if in_use {
+                progress!(sink, "Keeping scenery palette entry {} which is used by other scenery", recorded);
+                kept_palette_entries.push(recorded.clone());
+            } else {
+                progress!(sink, "Removing scenery palette entry {}", recorded);
+                marker_palette_indices.push(palette_index as u16);
+            }
+        }
+    }
+    remove_marker_palettes(scenario, &marker_palette_indices);
+
+    kept_palette_entries
+}
+
+/// Copies the markers recorded in the manifest from an earlier copy of the scenario into this one, along with their
+/// palette entries and names. This keeps markers when an output directory's scenario is rebuilt from the source by a
+/// run which doesn't place them. Everything copied is recorded in the new manifest.
+pub fn copy_recorded_markers(from: &Scenario, scenario: &mut Scenario, previous_manifest: &Manifest, manifest: &mut Manifest, sink: &dyn EventSink) -> Result<(), Error> {
+    let mut found: Vec<usize> = Vec::new();
+    for record in &previous_manifest.markers {
+        let Some(i) = find_recorded_marker(from, record, &found) else {
+            warning!(sink, "Recorded marker {} was already removed from the scenario", record.scenery_index);
+            continue;
+        };
+        found.push(i);
+        let marker = &from.scenery.items[i];
+        let marker_tag_path = marker._type
+            .and_then(|palette_index| from.scenery_palette.items.get(palette_index as usize))
+            .and_then(|palette_entry| palette_entry.name.path())
+            .expect("recorded markers are found by their palette entry");
+
+        let marker_palette_index = match get_marker_palette(scenario, marker_tag_path) {
+            Some(index) => index,
+            None => {
+                scenario.scenery_palette.items.push(ScenarioSceneryPalette {
+                    name: TagReference::Set(marker_tag_path.clone())
+                });
+                if !manifest.scenery_palette.iter().any(|recorded| recorded == marker_tag_path.path()) {
+                    manifest.scenery_palette.push(marker_tag_path.path().to_owned());
+                }
+                Some(scenario.scenery_palette.items.len() as u16 - 1)
+            }
+        };
+        let name_index = match &record.name {
+            Some(name) => Some(add_object_name(scenario, name)?),
+            None => None,
+        };
+        manifest.markers.push(MarkerRecord { scenery_index: scenario.scenery.items.len(), ..record.clone() });
+        scenario.scenery.items.push(ScenarioScenery {
+            _type: marker_palette_index,
+            name: name_index,
+            ..marker.clone()
+        });
+    }
+    progress!(sink, "Kept {} markers from the earlier run", found.len());
+    Ok(())
+}
+
+//markers are placed exactly at their spawn, unless they were snapped to the ground
+pub(crate) fn is_at_spawn(scenery: &ScenarioScenery, spawns: &[SpawnInfo]) -> bool {
+    let position = scenery.placement.position;
+    spawns.iter().any(|spawn| {
+        (position.x - spawn.position.x).abs() < 0.001
+            && (position.y - spawn.position.y).abs() < 0.001
+            && (position.z - spawn.position.z).abs() < 0.001
+    })
+}
+
+pub(crate) fn find_recorded_marker(scenario: &Scenario, record: &MarkerRecord, found: &[usize]) -> Option<usize> {
+    let is_match = |i: usize| {
+        scenario.scenery.items.get(i).map(|scenery| {
+            let palette_path = scenery._type
+                .and_then(|palette_index| scenario.scenery_palette.items.get(palette_index as usize))
+                .and_then(|palette_entry| palette_entry.name.path());
+            let position = scenery.placement.position;
+            let same_position = (position.x - record.position[0]).abs() < 0.001
+                && (position.y - record.position[1]).abs() < 0.001
+                && (position.z - record.position[2]).abs() < 0.001;
+            palette_path.map(|tag_path| tag_path.path() == record.marker).unwrap_or(false) && same_position && !found.contains(&i)
+        }).unwrap_or(false)
+    };
+
+    //the recorded index is checked first since it's usually still correct
+    if is_match(record.scenery_index) {
+        Some(record.scenery_index)
+    } else {
+        (0..scenario.scenery.items.len()).find(|i| is_match(*i))
+    }
+}
+
+fn get_marker_palette(scenario: &Scenario, marker_tag_path: &TagPath) -> Option<Index> {
+    scenario.scenery_palette.items.iter()
+        .position(|palette_entry| palette_entry.name.path().map(|tag_path| tag_path.eq(marker_tag_path)).unwrap_or(false))
+        .map(|i| Some(i as u16))
+}
+
+fn get_marker_palettes(scenario: &Scenario, marker_tag_paths: &[&TagPath]) -> Vec<u16> {
+    marker_tag_paths.iter()
+        .filter_map(|marker_tag_path| get_marker_palette(scenario, marker_tag_path).flatten())
+        .collect()
+}
+
+fn remove_all_markers(scenario: &mut Scenario, marker_palette_indices: &[u16], sink: &dyn EventSink) {
+    let marker_scenery_indices: Vec<usize> = scenario.scenery.items.iter().enumerate()
+        .filter(|(_, scenery)| scenery._type.map(|scenery_type| marker_palette_indices.contains(&scenery_type)).unwrap_or(false))
+        .map(|(i, _)| i)
+        .collect();
+    remove_scenery(scenario, &marker_scenery_indices, sink);
+}
+
+/// Removes scenery placements along with their object names. Scripts refer to objects by name index, so names are never
+/// renumbered: a name is only removed if it's at the end of the list, and otherwise it's left unused.
+pub(crate) fn remove_scenery(scenario: &mut Scenario, scenery_indices: &[usize], sink: &dyn EventSink) {
+    let mut name_indices: Vec<u16> = scenery_indices.iter()
+        .filter_map(|i| scenario.scenery.items.get(*i))
+        .filter_map(|scenery| scenery.name)
+        .collect();
+
+    scenario.scenery.items = scenario.scenery.items.iter().enumerate()
+        .filter(|(i, _)| !scenery_indices.contains(i))
+        .map(|(_, scenery)| scenery.clone())
+        .collect();
+
+    //names are removed from the end of the list down, stopping at the first one which is still needed
+    name_indices.sort_unstable_by(|a, b| b.cmp(a));
+    name_indices.dedup();
+    for i in name_indices {
+        if i as usize + 1 == scenario.object_names.items.len() && !is_object_name_used(scenario, i) {
+            scenario.object_names.items.pop();
+        } else if let Some(object_name) = scenario.object_names.items.get(i as usize) {
+            progress!(sink, "Keeping object name {} since removing it would renumber the names after it", object_name.name.as_str());
+        }
+    }
+}
+pub(crate) fn remove_marker_palettes(scenario: &mut Scenario, marker_palette_indices: &[u16]) {
+    //remove from the highest index down so the remaining indices stay valid
+    let mut marker_palette_indices = marker_palette_indices.to_vec();
+    marker_palette_indices.sort_unstable_by(|a, b| b.cmp(a));
+    marker_palette_indices.dedup();
+
+    marker_palette_indices.into_iter().for_each(|i| {
+        scenario.scenery_palette.items.remove(i as usize);
+
+        //any scenery using a palette index greater than i needs to be reduced
+        scenario.scenery.items.iter_mut().for_each(|scenery| {
+            if let Some(scenery_type) = scenery._type {
+                if scenery_type > i {
+                    scenery._type = Some(scenery_type - 1);
+                }
+            }
+        });
+    });
+}
+
+//spawn_012_slayer_red, using the spawn's index in the player starting locations
+fn get_marker_name(spawn: &SpawnInfo) -> String {
+    let spawn_type = spawn.types.iter()
+        .find(|spawn_type| **spawn_type != ScenarioSpawnType::None)
+        .map(|spawn_type| get_spawn_type_label(*spawn_type))
+        .unwrap_or("none");
+    let team = match spawn.team_index {
+        0 => "red".to_owned(),
+        1 => "blue".to_owned(),
+        team_index => format!("team{}", team_index),
+    };
+    format!("spawn_{:03}_{}_{}", spawn.index, spawn_type, team)
+}
+
+const MAX_OBJECT_NAME_LENGTH: usize = 31;
+
+//kept short since object names are limited to 31 characters
+const SPAWN_TYPE_LABELS: [(ScenarioSpawnType, &str); 8] = [
+    (ScenarioSpawnType::Ctf, "ctf"),
+    (ScenarioSpawnType::Slayer, "slayer"),
+    (ScenarioSpawnType::Oddball, "oddball"),
+    (ScenarioSpawnType::KingOfTheHill, "koth"),
+    (ScenarioSpawnType::Race, "race"),
+    (ScenarioSpawnType::AllGames, "all"),
+    (ScenarioSpawnType::AllExceptCtf, "noctf"),
+    (ScenarioSpawnType::AllExceptRaceAndCtf, "noracectf"),
+];
+
+pub(crate) fn get_spawn_type_label(spawn_type: ScenarioSpawnType) -> &'static str {
+    SPAWN_TYPE_LABELS.iter()
+        .find(|(labelled_type, _)| *labelled_type == spawn_type)
+        .map(|(_, label)| *label)
+        .unwrap_or("other")
+}
+
+/// Parses a spawn type label as given to `--marker-type`, like `ctf`, ignoring case.
+pub fn parse_spawn_type(label: &str) -> Result<ScenarioSpawnType, Error> {
+    SPAWN_TYPE_LABELS.iter()
+        .find(|(_, known_label)| known_label.eq_ignore_ascii_case(label))
+        .map(|(spawn_type, _)| *spawn_type)
+        .ok_or(Error::Options(format!(
+            "Not a valid spawn type: {}. Expected one of: {}",
+            label,
+            SPAWN_TYPE_LABELS.iter().map(|(_, known_label)| *known_label).collect::<Vec<_>>().join(", ")
+        )))
+}
+
+//Sapien rejects duplicate object names, so a name already used by another object, for example a marker of another
+//marker set or one the user named by hand, gets a numbered suffix
+fn get_unique_object_name(scenario: &Scenario, name: &str) -> String {
+    let is_taken = |candidate: &str| scenario.object_names.items.iter().enumerate()
+        .any(|(i, object_name)| object_name.name.as_str().eq_ignore_ascii_case(candidate) && is_object_name_used(scenario, i as u16));
+    if !is_taken(name) {
+        return name.to_owned();
+    }
+    (2..)
+        .map(|n| {
+            let suffix = format!("_{}", n);
+            //names are limited to 31 characters, so the suffix replaces the end of long names
+            let base: String = name.chars().take(MAX_OBJECT_NAME_LENGTH - suffix.len()).collect();
+            format!("{}{}", base, suffix)
+        })
+        .find(|candidate| !is_taken(candidate))
+        .expect("there are fewer object names than numbers")
+}
+
+//a name left unused by an earlier reset is reused, so names don't pile up and scripts using it find the marker again
+fn add_object_name(scenario: &mut Scenario, name: &str) -> Result<u16, Error> {
+    let unused_index = (0..scenario.object_names.items.len() as u16)
+        .find(|i| scenario.object_names.items[*i as usize].name.as_str().eq_ignore_ascii_case(name) && !is_object_name_used(scenario, *i));
+    if let Some(i) = unused_index {
+        return Ok(i);
+    }
+    scenario.object_names.items.push(ScenarioObjectName {
+        name: String32::from_str(name).map_err(|_| Error::Options(format!("Not a valid object name: {}", name)))?,
+        ..ScenarioObjectName::default()
+    });
+    Ok(scenario.object_names.items.len() as u16 - 1)
+}
+
+fn is_object_name_used(scenario: &Scenario, name_index: u16) -> bool {
+    get_object_name_indices(scenario).any(|name| *name == Some(name_index))
+}
+
+fn get_object_name_indices(scenario: &Scenario) -> impl Iterator<Item = &Index> {
+    scenario.scenery.items.iter().map(|object| &object.name)
+        .chain(scenario.bipeds.items.iter().map(|object| &object.name))
+        .chain(scenario.vehicles.items.iter().map(|object| &object.name))
+        .chain(scenario.equipment.items.iter().map(|object| &object.name))
+        .chain(scenario.weapons.items.iter().map(|object| &object.name))
+        .chain(scenario.machines.items.iter().map(|object| &object.name))
+        .chain(scenario.controls.items.iter().map(|object| &object.name))
+        .chain(scenario.light_fixtures.items.iter().map(|object| &object.name))
+        .chain(scenario.sound_scenery.items.iter().map(|object| &object.name))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::output::Discard;
+    use ringhopper::primitives::primitive::{TagGroup, Vector3D};
+
+    fn spawn(index: usize) -> SpawnInfo {
+        SpawnInfo {
+            index,
+            position: Vector3D { x: index as f32, y: 0.0, z: 0.0 },
+            facing: Angle::default(),
+            team_index: 0,
+            types: [ScenarioSpawnType::Slayer, ScenarioSpawnType::None, ScenarioSpawnType::None, ScenarioSpawnType::None],
+        }
+    }
+
+    fn marker(name: &str) -> TagPath {
+        TagPath::new(&format!("scenery\\{}\\{}", name, name), TagGroup::Scenery).unwrap()
+    }
+
+    fn marker_set() -> MarkerSet {
+        MarkerSet {
+            default: marker("spawn_marker"),
+            types: Vec::new(),
+            teams: Vec::new(),
+            named: true,
+            create_missing: false,
+        }
+    }
+
+    #[test]
+    fn type_marker_beats_team_marker_beats_default() {
+        let markers = MarkerSet {
+            types: vec![(ScenarioSpawnType::Ctf, marker("ctf")), (ScenarioSpawnType::AllGames, marker("all"))],
+            teams: vec![(1, marker("blue"))],
+            ..marker_set()
+        };
+        let with = |team_index: u16, types: [ScenarioSpawnType; 4]| SpawnInfo { team_index, types, ..spawn(0) };
+        let none = ScenarioSpawnType::None;
+
+        assert_eq!(markers.get_marker(&with(0, [ScenarioSpawnType::Slayer, none, none, none])), &marker("spawn_marker"));
+        assert_eq!(markers.get_marker(&with(1, [ScenarioSpawnType::Slayer, none, none, none])), &marker("blue"));
+        assert_eq!(markers.get_marker(&with(1, [ScenarioSpawnType::Slayer, ScenarioSpawnType::Ctf, none, none])), &marker("ctf"));
+        //with several mapped types the first slot wins
+        assert_eq!(markers.get_marker(&with(0, [ScenarioSpawnType::AllGames, ScenarioSpawnType::Ctf, none, none])), &marker("all"));
+        assert!(markers.has_type_marker(&with(0, [none, none, none, ScenarioSpawnType::Ctf])));
+        assert!(!markers.has_type_marker(&with(1, [ScenarioSpawnType::Slayer, none, none, none])));
+    }
+
+    //a biped the user named, after any names already in the scenario
+    fn add_named_biped(scenario: &mut Scenario, name: &str) -> u16 {
+        let name_index = add_object_name(scenario, name).unwrap();
+        scenario.bipeds.items.push(Default::default());
+        scenario.bipeds.items.last_mut().unwrap().name = Some(name_index);
+        name_index
+    }
+
+    fn get_names(scenario: &Scenario) -> Vec<&str> {
+        scenario.object_names.items.iter().map(|object_name| object_name.name.as_str()).collect()
+    }
+
+    #[test]
+    fn without_manifest_only_markers_at_spawns_are_removed() {
+        let mut scenario = Scenario::default();
+        let mut manifest = Manifest::default();
+        add_spawn_markers(&mut scenario, &[spawn(0), spawn(1)], &marker_set(), &mut manifest, &Discard).unwrap();
+        //a marker placed by hand away from any spawn
+        let mut by_hand = scenario.scenery.items[0].clone();
+        by_hand.name = None;
+        by_hand.placement.position = Vector3D { x: 5.0, y: 5.0, z: 0.0 };
+        scenario.scenery.items.push(by_hand);
+
+        assert_eq!(remove_markers_at_spawns(&mut scenario, &[spawn(0), spawn(1)], &marker_set(), &Discard), 1);
+        assert_eq!(scenario.scenery.items.len(), 1);
+        assert_eq!(scenario.scenery.items[0].placement.position.x, 5.0);
+    }
+
+    #[test]
+    fn trailing_marker_names_are_removed() {
+        let mut scenario = Scenario::default();
+        add_named_biped(&mut scenario, "bob");
+        let mut manifest = Manifest::default();
+        add_spawn_markers(&mut scenario, &[spawn(0), spawn(1)], &marker_set(), &mut manifest, &Discard).unwrap();
+        assert_eq!(get_names(&scenario), vec!["bob", "spawn_000_slayer_red", "spawn_001_slayer_red"]);
+
+        remove_recorded_markers(&mut scenario, &manifest, &Discard);
+        assert_eq!(get_names(&scenario), vec!["bob"]);
+        assert_eq!(scenario.bipeds.items[0].name, Some(0));
+    }
+
+    #[test]
+    fn names_after_markers_are_not_renumbered() {
+        let mut scenario = Scenario::default();
+        let mut manifest = Manifest::default();
+        add_spawn_markers(&mut scenario, &[spawn(0), spawn(1)], &marker_set(), &mut manifest, &Discard).unwrap();
+        let bob = add_named_biped(&mut scenario, "bob");
+
+        remove_recorded_markers(&mut scenario, &manifest, &Discard);
+        assert_eq!(get_names(&scenario), vec!["spawn_000_slayer_red", "spawn_001_slayer_red", "bob"]);
+        assert_eq!(scenario.bipeds.items[0].name, Some(bob));
+
+        //the next run reuses the names left behind rather than adding suffixed ones
+        let mut manifest = Manifest::default();
+        add_spawn_markers(&mut scenario, &[spawn(0), spawn(1)], &marker_set(), &mut manifest, &Discard).unwrap();
+        assert_eq!(get_names(&scenario).len(), 3);
+        let marker_names: Vec<Index> = scenario.scenery.items.iter().map(|scenery| scenery.name).collect();
+        assert_eq!(marker_names, vec![Some(0), Some(1)]);
+    }
+}
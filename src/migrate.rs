@@ -0,0 +1,205 @@
+use ringhopper::definitions::{Scenario, ScenarioStructureBSP};
+use crate::manifest::Manifest;
+use crate::markers::{find_recorded_marker, is_at_spawn, remove_marker_palettes, remove_scenery};
+use crate::output::{progress, EventSink};
+use crate::spawns::SpawnInfo;
+
+//tag paths of the marker scenery h1-spawn-tools injects. spawn-camp uses the same marker by default, so placements of
+//it are told apart by the manifest and position rather than by tag path alone. anything else, like its randoms
+//geometry, is only matched by patterns the user gives, since h1-spawn-tools doesn't fix their names and a loose match
+//would catch the level's own scenery and shaders
+const SPAWN_TOOLS_MARKER_TAG_PATHS: [&str; 1] = ["scenery\\spawn_marker_nhe\\spawn_marker_nhe"];
+
+pub struct SpawnToolsArtifacts {
+    //palette entries whose placements are all artifacts, so the entry can go too
+    pub palette_indices: Vec<u16>,
+    pub scenery_indices: Vec<usize>,
+    //placements of the marker scenery which aren't at a spawn, so they were likely placed by hand and are kept
+    pub kept_scenery_indices: Vec<usize>,
+    //shaders used by randoms geometry compiled into the BSP
+    pub bsp_shaders: Vec<String>,
+}
+
+impl SpawnToolsArtifacts {
+    pub fn is_empty(&self) -> bool {
+        self.palette_indices.is_empty() && self.scenery_indices.is_empty() && self.bsp_shaders.is_empty()
+    }
+}
+
+//finds markers and randoms not recorded in spawn-camp's manifest, which must have come from another tool. both tools
+//place markers at spawns, so an unrecorded marker elsewhere is left alone. patterns are matched anywhere in scenery and
+//shader tag paths, ignoring case
+pub fn find_spawn_tools_artifacts(scenario: &Scenario, bsp: &ScenarioStructureBSP, spawns: &[SpawnInfo], manifest: Option<&Manifest>, patterns: &[String]) -> SpawnToolsArtifacts {
+    let mut recorded_scenery_indices: Vec<usize> = Vec::new();
+    if let Some(manifest) = manifest {
+        manifest.markers.iter().for_each(|record| {
+            if let Some(i) = find_recorded_marker(scenario, record, &recorded_scenery_indices) {
+                recorded_scenery_indices.push(i);
+            }
+        });
+    }
+
+    let get_palette_path = |palette_index: u16| scenario.scenery_palette.items.get(palette_index as usize)
+        .and_then(|palette_entry| palette_entry.name.path())
+        .map(|tag_path| tag_path.path());
+
+    let mut scenery_indices: Vec<usize> = Vec::new();
+    let mut kept_scenery_indices: Vec<usize> = Vec::new();
+    for (i, scenery) in scenario.scenery.items.iter().enumerate() {
+        let Some(path) = scenery._type.and_then(get_palette_path) else {
+            continue;
+        };
+        if recorded_scenery_indices.contains(&i) {
+            continue;
+        }
+        if matches_pattern(path, patterns) {
+            scenery_indices.push(i);
+        } else if is_spawn_tools_marker(path) {
+            if is_at_spawn(scenery, spawns) {
+                scenery_indices.push(i);
+            } else {
+                kept_scenery_indices.push(i);
+            }
+        }
+    }
+
+    //palette entries still used by spawn-camp's own markers or by kept scenery stay
+    let palette_indices: Vec<u16> = (0..scenario.scenery_palette.items.len() as u16)
+        .filter(|palette_index| get_palette_path(*palette_index).is_some_and(|path| is_spawn_tools_path(path, patterns)))
+        .filter(|palette_index| {
+            scenario.scenery.items.iter().enumerate()
+                .filter(|(_, scenery)| scenery._type == Some(*palette_index))
+                .all(|(i, _)| scenery_indices.contains(&i))
+        })
+        .collect();
+
+    let mut bsp_shaders: Vec<String> = Vec::new();
+    bsp.lightmaps.items.iter()
+        .flat_map(|bsp_lightmap| bsp_lightmap.materials.items.iter())
+        .filter_map(|material| material.shader.path())
+        .filter(|shader_tag_path| is_spawn_tools_path(shader_tag_path.path(), patterns))
+        .for_each(|shader_tag_path| {
+            let shader = shader_tag_path.to_string();
+            if !bsp_shaders.contains(&shader) {
+                bsp_shaders.push(shader);
+            }
+        });
+
+    SpawnToolsArtifacts {
+        palette_indices,
+        scenery_indices,
+        kept_scenery_indices,
+        bsp_shaders,
+    }
+}
+
+pub fn print_spawn_tools_artifacts(scenario: &Scenario, artifacts: &SpawnToolsArtifacts, sink: &dyn EventSink) {
+    artifacts.palette_indices.iter().for_each(|palette_index| {
+        let placements = scenario.scenery.items.iter().filter(|scenery| scenery._type == Some(*palette_index)).count();
+        let tag_path = scenario.scenery_palette.items[*palette_index as usize].name.path().map(|tag_path| tag_path.to_string()).unwrap_or_default();
+        progress!(sink, "Found scenery palette entry {} {} with {} placements", palette_index, tag_path, placements);
+    });
+    artifacts.scenery_indices.iter().for_each(|i| {
+        let position = scenario.scenery.items[*i].placement.position;
+        progress!(sink, "Found injected scenery {} at ({:.3}, {:.3}, {:.3})", i, position.x, position.y, position.z);
+    });
+    artifacts.kept_scenery_indices.iter().for_each(|i| {
+        let position = scenario.scenery.items[*i].placement.position;
+        progress!(sink, "Keeping marker scenery {} at ({:.3}, {:.3}, {:.3}) which isn't at a spawn, in case it was placed by hand", i, position.x, position.y, position.z);
+    });
+    artifacts.bsp_shaders.iter().for_each(|shader| {
+        progress!(sink, "Found randoms geometry in the BSP using shader {}", shader);
+    });
+}
+
+pub fn remove_spawn_tools_artifacts(scenario: &mut Scenario, artifacts: &SpawnToolsArtifacts, sink: &dyn EventSink) {
+    remove_scenery(scenario, &artifacts.scenery_indices, sink);
+    remove_marker_palettes(scenario, &artifacts.palette_indices);
+}
+
+fn is_spawn_tools_path(path: &str, patterns: &[String]) -> bool {
+    is_spawn_tools_marker(path) || matches_pattern(path, patterns)
+}
+
+fn is_spawn_tools_marker(path: &str) -> bool {
+    SPAWN_TOOLS_MARKER_TAG_PATHS.iter().any(|tag_path| path.eq_ignore_ascii_case(tag_path))
+}
+
+fn matches_pattern(path: &str, patterns: &[String]) -> bool {
+    let path = path.to_ascii_lowercase();
+    patterns.iter().any(|pattern| !pattern.is_empty() && path.contains(&pattern.to_ascii_lowercase()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ringhopper::definitions::{ScenarioScenery, ScenarioSceneryPalette, ScenarioSpawnType};
+    use ringhopper::primitives::primitive::{Angle, TagGroup, TagPath, TagReference, Vector3D};
+    use crate::manifest::MarkerRecord;
+
+    const MARKER: &str = "scenery\\spawn_marker_nhe\\spawn_marker_nhe";
+
+    fn spawn(x: f32) -> SpawnInfo {
+        SpawnInfo {
+            index: 0,
+            position: Vector3D { x, y: 0.0, z: 0.0 },
+            facing: Angle::default(),
+            team_index: 0,
+            types: [ScenarioSpawnType::Slayer, ScenarioSpawnType::None, ScenarioSpawnType::None, ScenarioSpawnType::None],
+        }
+    }
+
+    //a scenario with the shared marker placed at each x
+    fn scenario(xs: &[f32]) -> Scenario {
+        let mut scenario = Scenario::default();
+        scenario.scenery_palette.items.push(ScenarioSceneryPalette { name: TagReference::Set(TagPath::new(MARKER, TagGroup::Scenery).unwrap()) });
+        for x in xs {
+            let mut scenery = ScenarioScenery { _type: Some(0), ..Default::default() };
+            scenery.placement.position = Vector3D { x: *x, y: 0.0, z: 0.0 };
+            scenario.scenery.items.push(scenery);
+        }
+        scenario
+    }
+
+    #[test]
+    fn markers_are_told_apart_by_manifest_and_position() {
+        //spawn-camp's marker at the first spawn, an injected one at the second and one placed by hand away from both
+        let scenario = scenario(&[1.0, 2.0, 9.0]);
+        let manifest = Manifest {
+            markers: vec![MarkerRecord { scenery_index: 0, marker: MARKER.to_owned(), position: [1.0, 0.0, 0.0], name: None }],
+            ..Manifest::default()
+        };
+        let artifacts = find_spawn_tools_artifacts(&scenario, &ScenarioStructureBSP::default(), &[spawn(1.0), spawn(2.0)], Some(&manifest), &[]);
+        assert_eq!(artifacts.scenery_indices, vec![1]);
+        assert_eq!(artifacts.kept_scenery_indices, vec![2]);
+        //the palette entry is still used by the other two
+        assert!(artifacts.palette_indices.is_empty());
+    }
+
+    #[test]
+    fn palette_entry_goes_when_every_placement_is_injected() {
+        let scenario = scenario(&[1.0, 2.0]);
+        let artifacts = find_spawn_tools_artifacts(&scenario, &ScenarioStructureBSP::default(), &[spawn(1.0), spawn(2.0)], None, &[]);
+        assert_eq!(artifacts.scenery_indices, vec![0, 1]);
+        assert_eq!(artifacts.palette_indices, vec![0]);
+    }
+
+    #[test]
+    fn known_spawn_tools_paths_match_in_any_case() {
+        assert!(is_spawn_tools_path("scenery\\spawn_marker_nhe\\spawn_marker_nhe", &[]));
+        assert!(is_spawn_tools_path("Scenery\\Spawn_Marker_NHE\\spawn_marker_nhe", &[]));
+        assert!(!is_spawn_tools_path("scenery\\spawn_marker_nhe\\spawn_marker_nhe_2", &[]));
+    }
+
+    #[test]
+    fn patterns_match_anywhere_in_the_path() {
+        let patterns = vec!["Randoms".to_owned()];
+        assert!(is_spawn_tools_path("levels\\test\\bloodgulch\\shaders\\randoms_lm", &patterns));
+        assert!(!is_spawn_tools_path("levels\\test\\bloodgulch\\shaders\\grass", &patterns));
+    }
+
+    #[test]
+    fn empty_patterns_match_nothing() {
+        assert!(!is_spawn_tools_path("levels\\test\\bloodgulch\\shaders\\grass", &[String::new()]));
+    }
+}
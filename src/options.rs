@@ -0,0 +1,142 @@
+use std::str::FromStr;
+use clap::ArgMatches;
+use hex_color::HexColor;
+use ringhopper::definitions::ScenarioSpawnType;
+use ringhopper::primitives::primitive::{TagGroup, TagPath};
+use spawn_camp::dither::DitherMode;
+use spawn_camp::error::Error;
+use spawn_camp::ground::GroundSnapping;
+use spawn_camp::lm_bitmap::LmScaling;
+use spawn_camp::lm_render::BlendMode;
+use spawn_camp::markers::{parse_spawn_type, MarkerSet};
+use spawn_camp::randoms::RenderOptions;
+use spawn_camp::tags::parse_tag_path;
+use spawn_camp::run::{MarkerOptions, SpawnOptions};
+use crate::cli::SCALES;
+use crate::config::ResolvedArgs;
+
+pub fn parse_spawn_options(command: &str, args: &ResolvedArgs) -> Result<SpawnOptions, Error> {
+    let (render, markers) = match command {
+        "apply" => (Some(parse_render_options(args)?), Some(parse_marker_options(args)?)),
+        "render" => (Some(parse_render_options(args)?), None),
+        "markers" => (None, Some(parse_marker_options(args)?)),
+        //reset only needs to know which scenery are markers
+        "reset" => (None, Some(MarkerOptions { markers: parse_marker_set(args)?, ground_snapping: None })),
+        _ => (None, None),
+    };
+    Ok(SpawnOptions {
+        render,
+        markers,
+    })
+}
+
+pub fn get_patterns(matches: &ArgMatches) -> Vec<String> {
+    matches.get_many::<String>("pattern").map(|patterns| patterns.cloned().collect()).unwrap_or_default()
+}
+
+fn parse_render_options(args: &ResolvedArgs) -> Result<RenderOptions, Error> {
+    Ok(RenderOptions {
+        randoms_color: parse_hex_code(&args.get_one("randoms-color")?.unwrap())?,
+        blend_mode: parse_blend_mode(&args.get_one("blend")?.unwrap())?,
+        walkable_only: args.get_flag("walkable")?,
+        lm_scaling: LmScaling {
+            max_scale: parse_scale(&args.get_one("lm-scale")?.unwrap())?,
+            max_dimension: args.get_one("max-dimension")?.map(|raw| parse_max_dimension(&raw)).transpose()?,
+            max_size: args.get_one("max-size")?.map(|raw| parse_max_size(&raw)).transpose()?,
+        },
+        dither_mode: parse_dither_mode(&args.get_one("dither")?.unwrap())?,
+    })
+}
+
+fn parse_marker_options(args: &ResolvedArgs) -> Result<MarkerOptions, Error> {
+    let mut markers = parse_marker_set(args)?;
+    markers.named = args.get_flag("name-markers")?;
+    markers.create_missing = args.get_flag("create-marker")?;
+    let ground_snapping = if args.get_flag("ground-snap")? {
+        Some(GroundSnapping {
+            tolerance: parse_distance(&args.get_one("snap-tolerance")?.unwrap())?,
+            report_distance: parse_distance(&args.get_one("ground-report")?.unwrap())?,
+        })
+    } else {
+        None
+    };
+    Ok(MarkerOptions {
+        markers,
+        ground_snapping,
+    })
+}
+
+fn parse_marker_set(args: &ResolvedArgs) -> Result<MarkerSet, Error> {
+    Ok(MarkerSet {
+        default: parse_tag_path(&args.get_one("marker-tag-path")?.unwrap(), TagGroup::Scenery)?,
+        types: args.get_many("marker-type")?.iter()
+            .map(|raw| parse_marker_type(raw))
+            .collect::<Result<_, Error>>()?,
+        teams: [(0, "marker-red-tag-path"), (1, "marker-blue-tag-path")].iter()
+            .filter_map(|(team_index, arg)| args.get_one(arg).transpose().map(|raw| (*team_index, raw)))
+            .map(|(team_index, raw)| parse_tag_path(&raw?, TagGroup::Scenery).map(|tag_path| (team_index, tag_path)))
+            .collect::<Result<_, Error>>()?,
+        named: false,
+        create_missing: false,
+    })
+}
+
+fn parse_marker_type(raw: &str) -> Result<(ScenarioSpawnType, TagPath), Error> {
+    let (spawn_type, tag_path) = raw.split_once('=').ok_or_else(|| Error::Options(format!("Expected a marker type like ctf=tag-path: {}", raw)))?;
+    Ok((parse_spawn_type(spawn_type)?, parse_tag_path(tag_path, TagGroup::Scenery)?))
+}
+
+fn parse_hex_code(raw: &str) -> Result<HexColor, Error> {
+    let prefixed = if raw.starts_with("#") {
+        raw.into()
+    } else {
+        format!("#{raw}")
+    };
+    HexColor::parse(&prefixed).map_err(|_| Error::Options(format!("Not a valid hex color code: {}", raw)))
+}
+
+//checked here as well as by clap, since the config file bypasses clap's parsing
+fn parse_scale(raw: &str) -> Result<u16, Error> {
+    if !SCALES.contains(&raw) {
+        return Err(Error::Options(format!("Not a valid lightmap scale: {} (expected one of {})", raw, SCALES.join(", "))));
+    }
+    Ok(u16::from_str(raw).unwrap())
+}
+
+fn parse_max_size(raw: &str) -> Result<usize, Error> {
+    match f64::from_str(raw) {
+        Ok(mib) if mib > 0.0 => Ok((mib * 1024.0 * 1024.0) as usize),
+        _ => Err(Error::Options(format!("Not a valid size in MiB: {}", raw))),
+    }
+}
+
+fn parse_max_dimension(raw: &str) -> Result<u16, Error> {
+    match u16::from_str(raw) {
+        Ok(px) if px > 0 => Ok(px),
+        _ => Err(Error::Options(format!("Not a valid dimension in pixels: {}", raw))),
+    }
+}
+
+fn parse_distance(raw: &str) -> Result<f32, Error> {
+    match f32::from_str(raw) {
+        Ok(units) if units >= 0.0 => Ok(units),
+        _ => Err(Error::Options(format!("Not a valid distance in world units: {}", raw))),
+    }
+}
+
+fn parse_dither_mode(raw: &str) -> Result<DitherMode, Error> {
+    match raw.to_ascii_lowercase().as_str() {
+        "ordered" => Ok(DitherMode::Ordered),
+        "diffusion" => Ok(DitherMode::Diffusion),
+        "none" => Ok(DitherMode::None),
+        _ => Err(Error::Options(format!("Not a valid dither mode: {}", raw))),
+    }
+}
+
+fn parse_blend_mode(raw: &str) -> Result<BlendMode, Error> {
+    match raw.to_ascii_lowercase().as_str() {
+        "normal" => Ok(BlendMode::Normal),
+        "multiply" => Ok(BlendMode::Multiply),
+        _ => Err(Error::Options(format!("Not a valid blend mode: {}", raw))),
+    }
+}
@@ -0,0 +1,344 @@
+use serde::Serialize;
+use crate::error::Error;
+use crate::export::SpawnExport;
+use crate::lm_bitmap::to_mib;
+use crate::plan::{ChangePlan, ManifestChange};
+use crate::report::{SpawnPair, SpawnReport, FAR_DISTANCE, NEAR_DISTANCE};
+
+//with --output json every event is printed to stdout as one JSON object per line. event and field names are
+//relied on by build scripts, so they should only ever be added to, not renamed
+/// Something to tell the user, printed as text or emitted as a JSON object with an `event` field naming the variant.
+#[derive(Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum Event<'a> {
+    /// Free-form progress text.
+    Message {
+        /// The text, which may be empty to separate sections of text output.
+        text: String,
+    },
+    /// Something which didn't stop the run but may need looking at.
+    Warning {
+        /// The warning, without a prefix.
+        text: String,
+    },
+    /// A scenario of a batch or watch run is about to be processed.
+    ScenarioStarted {
+        /// Tag path of the scenario.
+        scenario: &'a str,
+    },
+    /// A scenario of a batch or watch run was processed.
+    ScenarioFinished {
+        /// Tag path of the scenario.
+        scenario: &'a str,
+        /// Whether the scenario was processed successfully.
+        ok: bool,
+        /// Summary of what was done, if successful.
+        #[serde(skip_serializing_if = "Option::is_none")]
+        message: Option<&'a str>,
+        /// What went wrong, if unsuccessful.
+        #[serde(skip_serializing_if = "Option::is_none")]
+        error: Option<ErrorInfo<'a>>,
+        /// How long processing the scenario took.
+        duration_seconds: f64,
+    },
+    /// A tag was read.
+    TagRead {
+        /// Tag path with its group extension.
+        tag: String,
+    },
+    /// A tag was written, or would be in a dry run.
+    TagWritten {
+        /// Tag path with its group extension.
+        tag: String,
+        /// The tags directory written to.
+        dir: String,
+        /// The read-only layer the written tag takes precedence over, if any.
+        #[serde(skip_serializing_if = "Option::is_none")]
+        overrides: Option<String>,
+        /// Whether this was a dry run, so nothing was written.
+        dry_run: bool,
+    },
+    /// A tag was deleted, or would be in a dry run.
+    TagDeleted {
+        /// Tag path with its group extension.
+        tag: String,
+        /// The tags directory deleted from.
+        dir: String,
+        /// Whether this was a dry run, so nothing was deleted.
+        dry_run: bool,
+    },
+    /// A scenario's manifest was written, or would be in a dry run.
+    ManifestWritten {
+        /// File path of the manifest.
+        path: String,
+        /// Whether this was a dry run, so nothing was written.
+        dry_run: bool,
+    },
+    /// A scenario's manifest was deleted, or would be in a dry run.
+    ManifestDeleted {
+        /// File path of the manifest.
+        path: String,
+        /// Whether this was a dry run, so nothing was deleted.
+        dry_run: bool,
+    },
+    /// The scenario's spawns were read.
+    Spawns {
+        /// Every player starting location.
+        total: usize,
+        /// Starting locations used in slayer.
+        slayer: usize,
+    },
+    /// Spawn markers are being added to the scenario.
+    MarkersPlaced {
+        /// How many markers.
+        count: usize,
+    },
+    /// A lightmap page was rendered.
+    PageRendered {
+        /// Bitmap data index of the page.
+        page: u16,
+        /// Scale of the page compared to the original.
+        scale: u16,
+        /// Width of the rendered page in pixels.
+        width: u16,
+        /// Height of the rendered page in pixels.
+        height: u16,
+        /// Lightmap vertices rendered.
+        vertices: usize,
+    },
+    /// The randoms lightmap bitmap was assembled from the rendered pages.
+    LightmapAssembled {
+        /// Size of the pixel data alone.
+        size_bytes: usize,
+        /// Size of the whole tag file.
+        tag_size_bytes: usize,
+    },
+    /// Changes a dry run would make.
+    Plan {
+        /// The planned changes.
+        plan: &'a ChangePlan,
+    },
+    /// A scenario's spawns, for other tools.
+    Export {
+        /// The exported spawns.
+        export: &'a SpawnExport,
+    },
+    /// Statistics about a scenario's spawn layout.
+    Report {
+        /// The report.
+        report: &'a SpawnReport,
+    },
+    /// Always the last event of a run.
+    Result {
+        /// Whether the run was successful.
+        ok: bool,
+        /// Summary of what was done, if successful.
+        #[serde(skip_serializing_if = "Option::is_none")]
+        message: Option<&'a str>,
+        /// What went wrong, if unsuccessful.
+        #[serde(skip_serializing_if = "Option::is_none")]
+        error: Option<ErrorInfo<'a>>,
+    },
+}
+
+/// An error as emitted in events. The failing tag, page and BSP location are only included when known.
+#[derive(Serialize)]
+pub struct ErrorInfo<'a> {
+    /// See [`Error::kind`].
+    pub kind: &'static str,
+    /// The error's message.
+    pub message: String,
+    /// See [`Error::tag`].
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tag: Option<&'a str>,
+    /// See [`Error::page`].
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub page: Option<u16>,
+    /// BSP lightmap index of a malformed BSP.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub lightmap: Option<usize>,
+    /// Lightmap material index of a malformed BSP.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub material: Option<usize>,
+    /// Surface index of a malformed BSP.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub surface: Option<usize>,
+    /// See [`Error::exit_code`].
+    pub exit_code: u8,
+}
+
+impl<'a> ErrorInfo<'a> {
+    /// Describes the error.
+    pub fn new(err: &'a Error) -> ErrorInfo<'a> {
+        let (lightmap, material, surface) = match err {
+            Error::MalformedBsp { lightmap, material, surface, .. } => (*lightmap, *material, *surface),
+            _ => (None, None, None),
+        };
+        ErrorInfo {
+            kind: err.kind(),
+            message: err.to_string(),
+            tag: err.tag(),
+            page: err.page(),
+            lightmap,
+            material,
+            surface,
+            exit_code: err.exit_code(),
+        }
+    }
+}
+
+/// Where a run's events go. Every bit of output goes through one, so callers decide whether it's printed, collected or
+/// dropped. Closures taking an [`Event`] are sinks too.
+pub trait EventSink {
+    /// Handles one event.
+    fn emit(&self, event: Event);
+}
+
+impl<F: Fn(Event)> EventSink for F {
+    fn emit(&self, event: Event) {
+        self(event)
+    }
+}
+
+/// Prints events to stdout as text, or as one line of JSON each. This is what the binary uses.
+pub struct Printer {
+    /// Whether events are printed as JSON, from `--output json`.
+    pub json: bool,
+}
+
+impl EventSink for Printer {
+    fn emit(&self, event: Event) {
+        match self.json {
+            true => print_json(event),
+            false => print_text(event),
+        }
+    }
+}
+
+/// Drops every event, for callers which don't want any output.
+pub struct Discard;
+
+impl EventSink for Discard {
+    fn emit(&self, _: Event) {}
+}
+
+fn print_json(event: Event) {
+    //blank lines only separate text output
+    if !matches!(&event, Event::Message { text } if text.is_empty()) {
+        println!("{}", serde_json::to_string(&event).expect("events can always be serialized"));
+    }
+}
+
+fn print_text(event: Event) {
+
+    match event {
+        Event::Message { text } => println!("{}", text),
+        Event::Warning { text } => println!("WARNING: {}", text),
+        Event::ScenarioStarted { scenario } => {
+            println!();
+            println!("Processing scenario {}", scenario);
+        },
+        Event::ScenarioFinished { message: Some(message), .. } | Event::Result { message: Some(message), .. } => println!("{}", message),
+        Event::ScenarioFinished { error: Some(error), .. } | Event::Result { error: Some(error), .. } => eprintln!("ERROR: {}", error.message),
+        Event::TagWritten { tag, dry_run: true, .. } => println!("Would write tag {}", tag),
+        Event::TagWritten { tag, dir, overrides: Some(base_dir), .. } => println!("Writing tag {} to {}, taking precedence over {}", tag, dir, base_dir),
+        Event::TagWritten { tag, dir, .. } => println!("Writing tag {} to {}", tag, dir),
+        Event::TagDeleted { tag, dry_run: true, .. } => println!("Would delete tag {}", tag),
+        Event::TagDeleted { tag, dir, .. } => println!("Deleting tag {} from {}", tag, dir),
+        Event::ManifestWritten { path, dry_run: true } => println!("Would write manifest {}", path),
+        Event::ManifestWritten { path, .. } => println!("Writing manifest {}", path),
+        Event::ManifestDeleted { path, dry_run: true } => println!("Would delete manifest {}", path),
+        Event::ManifestDeleted { path, .. } => println!("Deleting manifest {}", path),
+        Event::Spawns { total, slayer } => println!("Found {} spawns, {} of which are used in slayer", total, slayer),
+        Event::MarkersPlaced { count } => println!("Placing {} spawn markers", count),
+        Event::PageRendered { page, scale, width, height, vertices } => println!("Rendered lightmap {} with {} verts at {}x scale [{}x{}]", page, vertices, scale, width, height),
+        Event::LightmapAssembled { size_bytes, tag_size_bytes } => println!("Randoms lightmap tag size is {:.2} MiB, of which {:.2} MiB is pixel data", to_mib(tag_size_bytes), to_mib(size_bytes)),
+        Event::Plan { plan } => print_plan(plan),
+        //exports are JSON either way, just pretty printed as text
+        Event::Export { export } => println!("{}", serde_json::to_string_pretty(export).expect("exports can always be serialized")),
+        Event::Report { report } => print_report(report),
+        //reading tags isn't reported in text
+        _ => (),
+    }
+}
+
+fn print_plan(plan: &ChangePlan) {
+    println!("Planned changes to {}:", plan.layers.write.display());
+    plan.tags.iter().for_each(|tag_path| println!("  Write tag {}", tag_path));
+    plan.deleted_tags.iter().for_each(|tag_path| println!("  Delete tag {}", tag_path));
+    match &plan.manifest {
+        Some(ManifestChange::Write(path)) => println!("  Write manifest {}", path),
+        Some(ManifestChange::Delete(path)) => println!("  Delete manifest {}", path),
+        None => (),
+    }
+    plan.scenery_palette_added.iter().for_each(|tag_path| println!("  Add scenery palette entry {}", tag_path));
+    plan.scenery_palette_removed.iter().for_each(|tag_path| println!("  Remove scenery palette entry {}", tag_path));
+    if let (Some(before), Some(after)) = (plan.scenery_before, plan.scenery_after) {
+        println!("  Scenery placements: {} -> {} ({:+})", before, after, after as i64 - before as i64);
+    }
+    plan.lightmap_pages.iter().for_each(|page| {
+        println!(
+            "  Lightmap page {}: {}x{} at {}x scale -> {}x{}",
+            page.index, page.original[0], page.original[1], page.scale, page.output[0], page.output[1]
+        );
+    });
+    if let Some(size) = plan.lightmap_size {
+        println!("  Estimated randoms lightmap tag size: {:.2} MiB", to_mib(size));
+    }
+}
+
+fn print_report(report: &SpawnReport) {
+    println!("Spawn report for {}:", report.scenario);
+    println!("  Spawns: {} ({} slayer)", report.total, report.slayer);
+    println!("  By type:");
+    report.types.iter().for_each(|type_count| println!("    {}: {}", type_count.spawn_type, type_count.count));
+    println!("  By team:");
+    report.teams.iter().for_each(|team_count| println!("    Team {}: {}", team_count.team_index, team_count.count));
+    println!("  Nearest slayer spawn:");
+    report.nearest.iter().for_each(|nearest| match (nearest.neighbour, nearest.distance) {
+        (Some(neighbour), Some(distance)) => println!("    Spawn {}: spawn {} at {:.3} units", nearest.index, neighbour, distance),
+        _ => println!("    Spawn {}: none", nearest.index),
+    });
+    println!("  Slayer spawns closer than {} unit(s):", NEAR_DISTANCE);
+    print_pairs(&report.stacked_pairs);
+    println!("  Slayer spawns {} to {} units apart:", NEAR_DISTANCE, FAR_DISTANCE);
+    print_pairs(&report.close_pairs);
+    println!("  Slayer spawns by cluster:");
+    report.clusters.iter().for_each(|cluster_count| match cluster_count.cluster {
+        Some(cluster) => println!("    Cluster {}: {}", cluster, cluster_count.count),
+        None => println!("    Outside the BSP: {}", cluster_count.count),
+    });
+    println!("  Slayer spawns by height:");
+    report.heights.iter().for_each(|band| {
+        println!("    {:>8.2} to {:>8.2}: {:>3} {}", band.from, band.to, band.count, "#".repeat(band.count));
+    });
+}
+
+fn print_pairs(pairs: &[SpawnPair]) {
+    if pairs.is_empty() {
+        println!("    None");
+    }
+    pairs.iter().for_each(|pair| println!("    Spawns {} and {}: {:.3} units", pair.a, pair.b, pair.distance));
+}
+
+/// Like `println!`, but emits a [`Event::Message`] to the sink given first, so it becomes a JSON event with
+/// `--output json`.
+#[macro_export]
+macro_rules! progress {
+    ($sink:expr) => {
+        $crate::output::EventSink::emit($sink, $crate::output::Event::Message { text: String::new() })
+    };
+    ($sink:expr, $($arg:tt)*) => {
+        $crate::output::EventSink::emit($sink, $crate::output::Event::Message { text: format!($($arg)*) })
+    };
+}
+pub use crate::progress;
+
+/// Like [`progress!`], but emits a [`Event::Warning`].
+#[macro_export]
+macro_rules! warning {
+    ($sink:expr, $($arg:tt)*) => {
+        $crate::output::EventSink::emit($sink, $crate::output::Event::Warning { text: format!($($arg)*) })
+    };
+}
+pub use crate::warning;
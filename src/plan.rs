@@ -0,0 +1,135 @@
+use std::fs;
+use std::path::Path;
+use serde::Serialize;
+use ringhopper::definitions::Scenario;
+use crate::backup::Backup;
+use crate::error::Error;
+use crate::layers::TagsLayers;
+use crate::lm_bitmap::Dimensions;
+use crate::output::{progress, EventSink};
+
+/// Everything a run changes. With `dry_run` set, tags and the manifest are only recorded here instead of being
+/// written.
+#[derive(Serialize, Default)]
+pub struct ChangePlan {
+    /// Whether to only record changes rather than making them.
+    #[serde(skip)]
+    pub dry_run: bool,
+    /// Where files are copied before they're overwritten or deleted.
+    #[serde(skip)]
+    pub backup: Option<Backup>,
+    /// Where tags are written to, and the layers they may take precedence over.
+    pub layers: TagsLayers,
+    /// Tag path of the scenario the changes are for.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub scenario: Option<String>,
+    /// Tag paths in the order they're written.
+    pub tags: Vec<String>,
+    /// Tag paths in the order they're deleted.
+    pub deleted_tags: Vec<String>,
+    /// What happens to the scenario's manifest, if anything.
+    pub manifest: Option<ManifestChange>,
+    /// Scenery palette entries added to the scenario.
+    pub scenery_palette_added: Vec<String>,
+    /// Scenery palette entries removed from the scenario.
+    pub scenery_palette_removed: Vec<String>,
+    /// Scenery placements in the scenario before the run.
+    pub scenery_before: Option<usize>,
+    /// Scenery placements in the scenario after the run.
+    pub scenery_after: Option<usize>,
+    /// Each rendered lightmap page.
+    pub lightmap_pages: Vec<PagePlan>,
+    /// Tag size of the randoms bitmap, including its header and page metadata.
+    pub lightmap_size: Option<usize>,
+}
+
+/// A change to a scenario's manifest, by file path.
+#[derive(Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ManifestChange {
+    /// The manifest is written.
+    Write(String),
+    /// The manifest is deleted.
+    Delete(String),
+}
+
+/// The scale and dimensions of a rendered lightmap page.
+#[derive(Serialize)]
+pub struct PagePlan {
+    /// Bitmap data index of the page.
+    pub index: u16,
+    /// Scale of the page compared to the original.
+    pub scale: u16,
+    /// Width and height of the original page.
+    pub original: [u16; 2],
+    /// Width and height of the rendered page.
+    pub output: [u16; 2],
+}
+
+impl PagePlan {
+    pub(crate) fn new(index: u16, scale: u16, original: Dimensions, output: Dimensions) -> Self {
+        PagePlan {
+            index,
+            scale,
+            original: [original.w, original.h],
+            output: [output.w, output.h],
+        }
+    }
+}
+
+//scenery palette and placement count taken before the scenario is changed, to diff against afterwards
+pub(crate) struct ScenarioSnapshot {
+    scenery_palette: Vec<String>,
+    scenery_count: usize,
+}
+
+impl ScenarioSnapshot {
+    pub fn new(scenario: &Scenario) -> Self {
+        ScenarioSnapshot {
+            scenery_palette: get_palette_paths(scenario),
+            scenery_count: scenario.scenery.items.len(),
+        }
+    }
+}
+
+impl ChangePlan {
+    /// A plan with nothing changed yet, for writing tags to the layers' write directory.
+    pub fn new(dry_run: bool, layers: TagsLayers) -> Self {
+        ChangePlan {
+            dry_run,
+            layers,
+            ..ChangePlan::default()
+        }
+    }
+
+    pub(crate) fn record_scenario(&mut self, before: &ScenarioSnapshot, after: &Scenario) {
+        let after_palette = get_palette_paths(after);
+        self.scenery_palette_added = after_palette.iter()
+            .filter(|tag_path| !before.scenery_palette.contains(tag_path))
+            .cloned()
+            .collect();
+        self.scenery_palette_removed = before.scenery_palette.iter()
+            .filter(|tag_path| !after_palette.contains(tag_path))
+            .cloned()
+            .collect();
+        self.scenery_before = Some(before.scenery_count);
+        self.scenery_after = Some(after.scenery.items.len());
+    }
+}
+
+/// Writes the plans to a file as JSON. A single scenario's plan is written as an object, and a batch as an array of them.
+pub fn write_plan_json(path: &Path, plans: &[ChangePlan], sink: &dyn EventSink) -> Result<(), Error> {
+    progress!(sink, "Writing plan {}", path.display());
+    let contents = match plans {
+        [plan] => serde_json::to_string_pretty(plan),
+        plans => serde_json::to_string_pretty(plans),
+    }.map_err(|err| Error::io(format!("Could not serialize plan: {}", err), err))?;
+    fs::write(path, contents).map_err(|err| Error::io(format!("Could not write plan {}: {}", path.display(), err), err))
+}
+
+fn get_palette_paths(scenario: &Scenario) -> Vec<String> {
+    scenario.scenery_palette.items.iter()
+        .filter_map(|palette_entry| palette_entry.name.path())
+        .map(|tag_path| tag_path.to_string())
+        .collect()
+}
@@ -0,0 +1,236 @@
+use hex_color::HexColor;
+use ringhopper::definitions::{Bitmap, ScenarioStructureBSP};
+use ringhopper::primitives::primitive::{TagGroup, TagPath, TagReference};
+use ringhopper::tag::scenario_structure_bsp::get_uncompressed_vertices_for_bsp_material;
+use crate::dither::{quantize_page, DitherMode};
+use crate::error::Error;
+use crate::lm_bitmap::{choose_page_scales, create_lm_bitmap, get_lm_page, get_page_data_size, get_tag_overhead_size, get_tag_size, validate_lm_bitmap, Dimensions, LmPage, LmScaling};
+use crate::lm_render::{BlendMode, LmRenderer, Vert, OUTPUT_BITMAP_DATA_FORMAT};
+use crate::manifest::LightmapRecord;
+use crate::output::{progress, warning, Event, EventSink};
+use crate::plan::{ChangePlan, PagePlan};
+use crate::spawns::SpawnInfo;
+use crate::tags::{display_ringhopper_err, open_tag, parse_tag_path, tag_group_err, write_tag, LayeredTags};
+
+/// How randoms are rendered into the lightmap.
+pub struct RenderOptions {
+    /// Color randoms are shaded with. Its alpha is how strongly they're blended over the lightmap.
+    pub randoms_color: HexColor,
+    /// How the color is blended over the lightmap.
+    pub blend_mode: BlendMode,
+    /// Whether only walkable surfaces up to 45 degrees steepness are shaded.
+    pub walkable_only: bool,
+    /// How much the pages are scaled up from the original lightmap's.
+    pub lm_scaling: LmScaling,
+    /// Dithering used when reducing rendered pages to 16-bit color.
+    pub dither_mode: DitherMode,
+}
+
+/// A randoms lightmap bitmap rendered over an original lightmap bitmap.
+pub struct RandomsLightmap {
+    /// A copy of the original bitmap with randoms rendered into the pages the BSP references.
+    pub bitmap: Bitmap,
+    /// The scale and dimensions of each rendered page.
+    pub pages: Vec<PagePlan>,
+}
+
+/// Renders randoms for the spawns into the BSP's lightmap and writes the result as the BSP's `_randoms` bitmap,
+/// pointing the BSP at it. The original lightmap is the one recorded by a previous run, if the BSP already references
+/// its randoms bitmap. Returns the record of what the BSP referenced before, for resetting it later.
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn generate_randoms(tags: &mut LayeredTags, renderer: &LmRenderer, spawns: &[SpawnInfo], bsp_tag_path: &TagPath, previous_lightmap: Option<&LightmapRecord>, options: &RenderOptions, plan: &mut ChangePlan, sink: &dyn EventSink) -> Result<LightmapRecord, Error> {
+    progress!(sink, "Generating randoms for BSP {} ", bsp_tag_path);
+    let mut bsp_tag = open_tag(tags.sources(), bsp_tag_path, sink)?;
+    let bsp = bsp_tag.get_mut::<ScenarioStructureBSP>().ok_or_else(|| tag_group_err(bsp_tag_path))?;
+
+    let original_lm_tag_path = get_original_lm_tag_path(bsp, bsp_tag_path, previous_lightmap, sink)?;
+    progress!(sink, "Rendering over original lightmap {}", original_lm_tag_path);
+    let original_lm_tag = open_tag(tags.sources(), &original_lm_tag_path, sink)?;
+    let original_lm = original_lm_tag.get_ref::<Bitmap>().ok_or_else(|| tag_group_err(&original_lm_tag_path))?;
+
+    let randoms_lm = render_randoms_lightmap(renderer, spawns, bsp, bsp_tag_path, original_lm, &original_lm_tag_path, options, sink)?;
+    plan.lightmap_pages.extend(randoms_lm.pages);
+    plan.lightmap_size = Some(get_tag_size(&randoms_lm.bitmap));
+    let output_lm_tag_path = get_output_lm_tag_path(bsp_tag_path);
+    write_tag(tags, &output_lm_tag_path, &randoms_lm.bitmap, plan, sink)?;
+
+    progress!(sink, "Updating BSP lightmap bitmap reference");
+    bsp.lightmaps_bitmap = TagReference::Set(output_lm_tag_path.clone());
+    write_tag(tags, bsp_tag_path, bsp, plan, sink)?;
+
+    Ok(LightmapRecord {
+        bsp: bsp_tag_path.path().to_owned(),
+        original: original_lm_tag_path.path().to_owned(),
+        randoms: output_lm_tag_path.path().to_owned(),
+    })
+}
+
+/// Writes the source BSP pointing at randoms rendered by an earlier run, for runs which only place markers into an
+/// output directory. The output directory's BSP is otherwise a copy from before the source BSP's latest edits.
+pub(crate) fn point_bsp_at_randoms(tags: &mut LayeredTags, lightmap: &LightmapRecord, plan: &mut ChangePlan, sink: &dyn EventSink) -> Result<(), Error> {
+    let bsp_tag_path = parse_tag_path(&lightmap.bsp, TagGroup::ScenarioStructureBSP)?;
+    let mut bsp_tag = open_tag(tags.sources(), &bsp_tag_path, sink)?;
+    let bsp = bsp_tag.get_mut::<ScenarioStructureBSP>().ok_or_else(|| tag_group_err(&bsp_tag_path))?;
+    progress!(sink, "Updating BSP lightmap bitmap reference to the earlier randoms");
+    bsp.lightmaps_bitmap = TagReference::Set(parse_tag_path(&lightmap.randoms, TagGroup::Bitmap)?);
+    write_tag(tags, &bsp_tag_path, bsp, plan, sink)
+}
+
+/// Renders randoms for the spawns over each page of the original lightmap bitmap which the BSP references, and
+/// assembles them into a new bitmap. Pages the BSP doesn't reference are copied as-is. The tag paths are only used
+/// to report where problems were found.
+#[allow(clippy::too_many_arguments)]
+pub fn render_randoms_lightmap(renderer: &LmRenderer, spawns: &[SpawnInfo], bsp: &ScenarioStructureBSP, bsp_tag_path: &TagPath, original_lm: &Bitmap, original_lm_tag_path: &TagPath, options: &RenderOptions, sink: &dyn EventSink) -> Result<RandomsLightmap, Error> {
+    check_lm_bitmap(original_lm, bsp, original_lm_tag_path)?;
+    renderer.set_style(options.randoms_color, options.blend_mode, options.walkable_only)?;
+    renderer.set_spawns(spawns)?;
+
+    //only pages referenced by the BSP are rendered; any others are copied as-is and count towards the size limit,
+    //along with the rest of the tag
+    let rendered_indices: Vec<u16> = bsp.lightmaps.items.iter().filter_map(|bsp_lightmap| bsp_lightmap.bitmap).collect();
+    let original_dimensions: Vec<Dimensions> = rendered_indices.iter().map(|lm_bitmap_index| {
+        let prev_lm_bitmap_data = &original_lm.bitmap_data.items[*lm_bitmap_index as usize];
+        Dimensions {
+            w: prev_lm_bitmap_data.width,
+            h: prev_lm_bitmap_data.height,
+        }
+    }).collect();
+    let fixed_size: usize = get_tag_overhead_size(original_lm) + original_lm.bitmap_data.items.iter().enumerate()
+        .filter(|(i, _)| !rendered_indices.contains(&(*i as u16)))
+        .map(|(_, prev_lm_bitmap_data)| get_page_data_size(Dimensions { w: prev_lm_bitmap_data.width, h: prev_lm_bitmap_data.height }, prev_lm_bitmap_data.format))
+        .sum::<usize>();
+    let page_scales = choose_page_scales(&original_dimensions, OUTPUT_BITMAP_DATA_FORMAT, fixed_size, &options.lm_scaling).map_err(Error::Options)?;
+
+    //pages are kept at the same bitmap data index the BSP's lightmaps reference
+    let mut output_pages: Vec<Option<LmPage>> = original_lm.bitmap_data.items.iter().map(|_| None).collect();
+    let mut pages: Vec<PagePlan> = Vec::new();
+    bsp.lightmaps.items.iter().enumerate()
+        .filter_map(|(lightmap_index, bsp_lightmap)| bsp_lightmap.bitmap.map(|lm_bitmap_index| (lightmap_index, bsp_lightmap, lm_bitmap_index)))
+        .zip(page_scales.iter().zip(original_dimensions.iter()))
+        .try_for_each(|((lightmap_index, bsp_lightmap, lm_bitmap_index), (scale, original_dimensions))| -> Result<(), Error> {
+            let mut verts: Vec<Vert> = Vec::new();
+            let mut indices: Vec<u16> = Vec::new();
+
+            //base the output dimensions on the original lightmap's dimensions
+            let output_dimensions = Dimensions {
+                w: original_dimensions.w * *scale,
+                h: original_dimensions.h * *scale,
+            };
+
+            for (material_index, material) in bsp_lightmap.materials.items.iter().enumerate() {
+                let bsp_err = |surface: Option<usize>, message: String| malformed_bsp_err(bsp_tag_path, Some(lightmap_index), Some(material_index), surface, message);
+                let (rendered_verts, lm_verts) = get_uncompressed_vertices_for_bsp_material(material)
+                    .map_err(|err| bsp_err(None, format!("its vertices could not be read: {}", display_ringhopper_err(err))))?;
+                let rendered_verts = rendered_verts.collect::<Vec<_>>();
+
+                //indices are 16-bit, so every material of the page together can't have more vertices than that
+                let too_many_verts_err = |surface: Option<usize>| bsp_err(surface, format!("its lightmap has more than {} vertices", u16::MAX));
+                let offset = u16::try_from(verts.len()).map_err(|_| too_many_verts_err(None))?;
+                for (i, v) in lm_verts.enumerate() {
+                    let rendered_vert = rendered_verts.get(i)
+                        .ok_or_else(|| bsp_err(None, format!("it has more lightmap vertices than the {} rendered vertices", rendered_verts.len())))?;
+                    verts.push(Vert {
+                        lm_uv: [
+                            v.texture_coords.x,
+                            v.texture_coords.y
+                        ],
+                        world_pos: [
+                            rendered_vert.position.x,
+                            rendered_vert.position.y,
+                            rendered_vert.position.z,
+                        ],
+                        world_normal: [
+                            rendered_vert.normal.x,
+                            rendered_vert.normal.y,
+                            rendered_vert.normal.z,
+                        ]
+                    });
+                }
+                let material_verts = verts.len() - offset as usize;
+
+                for surface_index in material.surfaces..(material.surfaces + material.surface_count) {
+                    let surface_index = surface_index as usize;
+                    let bsp_surface = bsp.surfaces.items.get(surface_index)
+                        .ok_or_else(|| bsp_err(Some(surface_index), format!("the BSP only has {} surfaces", bsp.surfaces.items.len())))?;
+                    for vertex_index in [bsp_surface.vertex0_index, bsp_surface.vertex1_index, bsp_surface.vertex2_index] {
+                        let vertex_index = vertex_index.ok_or_else(|| bsp_err(Some(surface_index), "the surface has a null vertex index".into()))?;
+                        if vertex_index as usize >= material_verts {
+                            return Err(bsp_err(Some(surface_index), format!("the surface's vertex index {} is out of range of the material's {} lightmap vertices", vertex_index, material_verts)));
+                        }
+                        indices.push(vertex_index.checked_add(offset).ok_or_else(|| too_many_verts_err(Some(surface_index)))?);
+                    }
+                }
+            }
+
+            let vertices = verts.len();
+            let original_lm_page = get_lm_page(original_lm, lm_bitmap_index)
+                .map_err(|message| Error::InvalidLightmap { tag: original_lm_tag_path.to_string(), page: Some(lm_bitmap_index), message })?;
+            let rendered_page = renderer.render_randoms(verts, indices, output_dimensions, &original_lm_page)
+                .map_err(|err| err.with_page(lm_bitmap_index))?;
+            sink.emit(Event::PageRendered { page: lm_bitmap_index, scale: *scale, width: output_dimensions.w, height: output_dimensions.h, vertices });
+            output_pages[lm_bitmap_index as usize] = Some(quantize_page(&rendered_page, options.dither_mode));
+            pages.push(PagePlan::new(lm_bitmap_index, *scale, *original_dimensions, output_dimensions));
+            Ok(())
+        })?;
+
+    progress!(sink, "Assembling LM bitmap");
+    let bitmap = create_lm_bitmap(original_lm, &output_pages)
+        .map_err(|message| Error::InvalidLightmap { tag: original_lm_tag_path.to_string(), page: None, message })?;
+    sink.emit(Event::LightmapAssembled { size_bytes: bitmap.processed_pixel_data.bytes.len(), tag_size_bytes: get_tag_size(&bitmap) });
+    Ok(RandomsLightmap {
+        bitmap,
+        pages,
+    })
+}
+
+/// Checks everything about the original lightmap bitmap which rendering relies on, reporting every problem found.
+pub(crate) fn check_lm_bitmap(lm_bitmap: &Bitmap, bsp: &ScenarioStructureBSP, lm_tag_path: &TagPath) -> Result<(), Error> {
+    let problems = validate_lm_bitmap(lm_bitmap, bsp);
+    if problems.is_empty() {
+        return Ok(());
+    }
+    Err(Error::InvalidLightmap {
+        tag: lm_tag_path.to_string(),
+        page: None,
+        message: format!("The lightmap bitmap {} has {} problem(s):\n  {}", lm_tag_path, problems.len(), problems.join("\n  ")),
+    })
+}
+
+/// The lightmap bitmap randoms are rendered over. This is the BSP's own reference unless a previous run already
+/// replaced it with the randoms bitmap, in which case it's the recorded original.
+pub(crate) fn get_original_lm_tag_path(bsp: &ScenarioStructureBSP, bsp_tag_path: &TagPath, previous_lightmap: Option<&LightmapRecord>, sink: &dyn EventSink) -> Result<TagPath, Error> {
+    let current_lm_tag_path = bsp.lightmaps_bitmap.path().ok_or_else(|| Error::MissingTag {
+        tag: bsp_tag_path.to_string(),
+        message: format!("The BSP {} has no lightmap bitmap, so it needs to be lightmapped first", bsp_tag_path),
+    })?;
+    if !is_output_lm_tag_path(current_lm_tag_path, bsp_tag_path) {
+        return Ok(current_lm_tag_path.clone());
+    }
+    match previous_lightmap {
+        Some(lightmap) if lightmap.bsp == bsp_tag_path.path() => parse_tag_path(&lightmap.original, TagGroup::Bitmap),
+        //runs from before manifests were recorded always rendered over tool's lightmap
+        _ => {
+            let tool_lm_tag_path = get_tool_lm_tag_path(bsp_tag_path);
+            warning!(sink, "BSP already references the randoms lightmap and no original was recorded, assuming {}", tool_lm_tag_path);
+            Ok(tool_lm_tag_path)
+        }
+    }
+}
+
+/// Tool creates lightmap bitmaps with the same tag path as the BSP.
+pub(crate) fn get_tool_lm_tag_path(bsp_tag_path: &TagPath) -> TagPath {
+    TagPath::new(bsp_tag_path.path(), TagGroup::Bitmap).unwrap()
+}
+
+/// The randoms bitmap is written next to the BSP, with a `_randoms` suffix.
+pub(crate) fn get_output_lm_tag_path(bsp_tag_path: &TagPath) -> TagPath {
+    TagPath::new(&format!("{}_randoms", bsp_tag_path.path()), TagGroup::Bitmap).unwrap()
+}
+
+pub(crate) fn is_output_lm_tag_path(lm_tag_path: &TagPath, bsp_tag_path: &TagPath) -> bool {
+    lm_tag_path.path().eq_ignore_ascii_case(get_output_lm_tag_path(bsp_tag_path).path())
+}
+
+pub(crate) fn malformed_bsp_err(bsp_tag_path: &TagPath, lightmap: Option<usize>, material: Option<usize>, surface: Option<usize>, message: String) -> Error {
+    Error::MalformedBsp { bsp: bsp_tag_path.to_string(), lightmap, material, surface, message }
+}
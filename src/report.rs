@@ -0,0 +1,271 @@
+use std::collections::BTreeMap;
+use serde::Serialize;
+use ringhopper::definitions::{ModelCollisionGeometryBSP, Scenario, ScenarioSpawnType, ScenarioStructureBSP};
+use ringhopper::primitives::primitive::Vector3D;
+use crate::spawns::{get_slayer_spawns, get_spawns, SpawnInfo};
+use crate::markers::get_spawn_type_label;
+
+/// Spawns closer together than this don't affect each other's randoms, matching the randoms shader.
+pub const NEAR_DISTANCE: f32 = 1.0;
+/// A point is only random if no slayer spawn is between `NEAR_DISTANCE` and this far from it.
+pub const FAR_DISTANCE: f32 = 6.0;
+/// Spawn heights are counted in bands of this many world units.
+pub const HEIGHT_BAND: f32 = 1.0;
+
+//bsp3d node children with the high bit set are leaves, and all bits set means outside the BSP
+const LEAF_FLAG: u32 = 0x80000000;
+const NO_CHILD: u32 = 0xFFFFFFFF;
+
+/// Statistics about a scenario's spawn layout. Type and team counts include every spawn, while the rest only cover
+/// slayer spawns since those are the ones randoms are rendered for.
+//field names are kept stable since other tools read these with --output json
+#[derive(Serialize)]
+pub struct SpawnReport {
+    /// Tag path of the scenario.
+    pub scenario: String,
+    /// Every player starting location.
+    pub total: usize,
+    /// Starting locations used in slayer.
+    pub slayer: usize,
+    /// Spawns with several types are counted once for each.
+    pub types: Vec<TypeCount>,
+    /// Spawns by team index.
+    pub teams: Vec<TeamCount>,
+    /// The nearest other slayer spawn to each slayer spawn.
+    pub nearest: Vec<NearestNeighbour>,
+    /// Pairs closer than `NEAR_DISTANCE`.
+    pub stacked_pairs: Vec<SpawnPair>,
+    /// Pairs at least `NEAR_DISTANCE` but closer than `FAR_DISTANCE` apart.
+    pub close_pairs: Vec<SpawnPair>,
+    /// Slayer spawns by the BSP cluster they're in.
+    pub clusters: Vec<ClusterCount>,
+    /// Slayer spawns by height, in bands of `HEIGHT_BAND`.
+    pub heights: Vec<HeightBand>,
+}
+
+/// How many spawns have a type.
+#[derive(Serialize)]
+pub struct TypeCount {
+    /// Label as accepted by `--marker-type`.
+    pub spawn_type: &'static str,
+    /// How many spawns.
+    pub count: usize,
+}
+
+/// How many spawns are on a team.
+#[derive(Serialize)]
+pub struct TeamCount {
+    /// Team index, where 0 is red and 1 is blue.
+    pub team_index: u16,
+    /// How many spawns.
+    pub count: usize,
+}
+
+/// The nearest other slayer spawn to a slayer spawn, by player starting location index.
+#[derive(Serialize)]
+pub struct NearestNeighbour {
+    /// The spawn.
+    pub index: usize,
+    /// The nearest other spawn, or None when there's only one slayer spawn.
+    pub neighbour: Option<usize>,
+    /// Distance to the nearest other spawn in world units.
+    pub distance: Option<f32>,
+}
+
+/// Two slayer spawns, by player starting location index.
+#[derive(Serialize)]
+pub struct SpawnPair {
+    /// The spawn with the lower index.
+    pub a: usize,
+    /// The spawn with the higher index.
+    pub b: usize,
+    /// Distance between them in world units.
+    pub distance: f32,
+}
+
+/// How many slayer spawns are in a BSP cluster.
+#[derive(Serialize)]
+pub struct ClusterCount {
+    /// Cluster index, or None for spawns outside the BSP.
+    pub cluster: Option<u16>,
+    /// How many spawns.
+    pub count: usize,
+}
+
+/// How many slayer spawns are in a band of heights.
+#[derive(Serialize)]
+pub struct HeightBand {
+    /// Lowest height in the band.
+    pub from: f32,
+    /// Height the band goes up to, exclusive.
+    pub to: f32,
+    /// How many spawns.
+    pub count: usize,
+}
+
+impl SpawnReport {
+    /// Reports on the scenario's spawns. The BSP's collision geometry is used to find which cluster each spawn is in.
+    pub fn new(scenario_tag_path: &str, scenario: &Scenario, bsp: &ScenarioStructureBSP, collision_bsp: &ModelCollisionGeometryBSP) -> SpawnReport {
+        let spawns = get_spawns(scenario);
+        let slayer_spawns = get_slayer_spawns(scenario);
+        let (stacked_pairs, close_pairs) = get_close_pairs(&slayer_spawns).into_iter()
+            .partition(|pair| pair.distance < NEAR_DISTANCE);
+        SpawnReport {
+            scenario: scenario_tag_path.to_owned(),
+            total: spawns.len(),
+            slayer: slayer_spawns.len(),
+            types: count_types(&spawns),
+            teams: count_by(&spawns, |spawn| spawn.team_index).into_iter()
+                .map(|(team_index, count)| TeamCount { team_index, count })
+                .collect(),
+            nearest: get_nearest_neighbours(&slayer_spawns),
+            stacked_pairs,
+            close_pairs,
+            clusters: count_by(&slayer_spawns, |spawn| find_cluster(bsp, collision_bsp, spawn.position)).into_iter()
+                .map(|(cluster, count)| ClusterCount { cluster, count })
+                .collect(),
+            heights: count_by(&slayer_spawns, |spawn| (spawn.position.z / HEIGHT_BAND).floor() as i32).into_iter()
+                .map(|(band, count)| HeightBand { from: band as f32 * HEIGHT_BAND, to: (band + 1) as f32 * HEIGHT_BAND, count })
+                .collect(),
+        }
+    }
+}
+
+fn count_types(spawns: &[SpawnInfo]) -> Vec<TypeCount> {
+    let mut counts = BTreeMap::new();
+    spawns.iter().for_each(|spawn| {
+        let mut labels: Vec<&'static str> = spawn.types.iter()
+            .filter(|spawn_type| **spawn_type != ScenarioSpawnType::None)
+            .map(|spawn_type| get_spawn_type_label(*spawn_type))
+            .collect();
+        labels.sort();
+        labels.dedup();
+        labels.into_iter().for_each(|label| *counts.entry(label).or_insert(0) += 1);
+    });
+    counts.into_iter().map(|(spawn_type, count)| TypeCount { spawn_type, count }).collect()
+}
+
+fn count_by<K: Ord>(spawns: &[SpawnInfo], key: impl Fn(&SpawnInfo) -> K) -> BTreeMap<K, usize> {
+    let mut counts = BTreeMap::new();
+    spawns.iter().for_each(|spawn| *counts.entry(key(spawn)).or_insert(0) += 1);
+    counts
+}
+
+fn get_nearest_neighbours(spawns: &[SpawnInfo]) -> Vec<NearestNeighbour> {
+    spawns.iter().map(|spawn| {
+        let nearest = spawns.iter()
+            .filter(|other| other.index != spawn.index)
+            .map(|other| (other.index, distance(spawn.position, other.position)))
+            .min_by(|(_, a), (_, b)| a.total_cmp(b));
+        NearestNeighbour {
+            index: spawn.index,
+            neighbour: nearest.map(|(index, _)| index),
+            distance: nearest.map(|(_, distance)| distance),
+        }
+    }).collect()
+}
+
+fn get_close_pairs(spawns: &[SpawnInfo]) -> Vec<SpawnPair> {
+    spawns.iter().enumerate()
+        .flat_map(|(i, a)| spawns[i + 1..].iter().map(move |b| SpawnPair { a: a.index, b: b.index, distance: distance(a.position, b.position) }))
+        .filter(|pair| pair.distance < FAR_DISTANCE)
+        .collect()
+}
+
+fn distance(a: Vector3D, b: Vector3D) -> f32 {
+    let (dx, dy, dz) = (a.x - b.x, a.y - b.y, a.z - b.z);
+    (dx * dx + dy * dy + dz * dz).sqrt()
+}
+
+//walks the collision bsp3d to the leaf containing the point. structure BSP leaves match collision leaves by index
+fn find_cluster(bsp: &ScenarioStructureBSP, collision_bsp: &ModelCollisionGeometryBSP, point: Vector3D) -> Option<u16> {
+    let nodes = &collision_bsp.bsp3d_nodes.items;
+    let planes = &collision_bsp.planes.items;
+    let mut child = if nodes.is_empty() { NO_CHILD } else { 0 };
+    //bounded by the node count in case the tree is malformed
+    for _ in 0..nodes.len() {
+        if child == NO_CHILD || child & LEAF_FLAG != 0 {
+            break;
+        }
+        let node = nodes.get(child as usize)?;
+        let plane = planes.get(node.plane as usize)?;
+        let side = plane.vector.x * point.x + plane.vector.y * point.y + plane.vector.z * point.z - plane.offset;
+        child = if side >= 0.0 { node.front_child } else { node.back_child };
+    }
+    if child == NO_CHILD || child & LEAF_FLAG == 0 {
+        return None;
+    }
+    bsp.leaves.items.get((child & !LEAF_FLAG) as usize)?.cluster
+}
+
+#[cfg(test)]
+mod tests {
+    use ringhopper::definitions::{ModelCollisionGeometryBSP3DNode, ScenarioStructureBSPLeaf};
+    use ringhopper::primitives::primitive::{Angle, Plane3D, Reflexive};
+    use super::*;
+
+    fn spawn(index: usize, x: f32, y: f32, z: f32) -> SpawnInfo {
+        SpawnInfo {
+            index,
+            position: Vector3D { x, y, z },
+            facing: Angle::default(),
+            team_index: 0,
+            types: [ScenarioSpawnType::Slayer, ScenarioSpawnType::None, ScenarioSpawnType::None, ScenarioSpawnType::None],
+        }
+    }
+
+    //one plane at x = 0, with cluster 7 behind it and cluster 2 in front
+    fn split_bsp(back_child: u32) -> (ScenarioStructureBSP, ModelCollisionGeometryBSP) {
+        let bsp = ScenarioStructureBSP {
+            leaves: Reflexive::new(vec![
+                ScenarioStructureBSPLeaf { cluster: Some(7), ..ScenarioStructureBSPLeaf::default() },
+                ScenarioStructureBSPLeaf { cluster: Some(2), ..ScenarioStructureBSPLeaf::default() },
+            ]),
+            ..ScenarioStructureBSP::default()
+        };
+        let collision_bsp = ModelCollisionGeometryBSP {
+            bsp3d_nodes: Reflexive::new(vec![ModelCollisionGeometryBSP3DNode { plane: 0, back_child, front_child: LEAF_FLAG | 1 }]),
+            planes: Reflexive::new(vec![Plane3D { vector: Vector3D { x: 1.0, y: 0.0, z: 0.0 }, offset: 0.0 }]),
+            ..ModelCollisionGeometryBSP::default()
+        };
+        (bsp, collision_bsp)
+    }
+
+    #[test]
+    fn spawns_are_found_in_the_leaf_cluster_on_their_side() {
+        let (bsp, collision_bsp) = split_bsp(LEAF_FLAG);
+        assert_eq!(find_cluster(&bsp, &collision_bsp, Vector3D { x: 5.0, y: 0.0, z: 0.0 }), Some(2));
+        assert_eq!(find_cluster(&bsp, &collision_bsp, Vector3D { x: -5.0, y: 0.0, z: 0.0 }), Some(7));
+    }
+
+    #[test]
+    fn spawns_outside_the_bsp_have_no_cluster() {
+        let (bsp, collision_bsp) = split_bsp(NO_CHILD);
+        assert_eq!(find_cluster(&bsp, &collision_bsp, Vector3D { x: -5.0, y: 0.0, z: 0.0 }), None);
+        assert_eq!(find_cluster(&bsp, &ModelCollisionGeometryBSP::default(), Vector3D { x: 5.0, y: 0.0, z: 0.0 }), None);
+    }
+
+    #[test]
+    fn close_pairs_stop_at_far_distance() {
+        let spawns = [spawn(0, 0.0, 0.0, 0.0), spawn(2, 0.5, 0.0, 0.0), spawn(5, 0.0, 4.0, 0.0), spawn(6, 20.0, 0.0, 0.0)];
+        let pairs: Vec<(usize, usize)> = get_close_pairs(&spawns).iter().map(|pair| (pair.a, pair.b)).collect();
+        assert_eq!(pairs, vec![(0, 2), (0, 5), (2, 5)]);
+    }
+
+    #[test]
+    fn nearest_neighbours_skip_the_spawn_itself() {
+        let spawns = [spawn(0, 0.0, 0.0, 0.0), spawn(1, 3.0, 4.0, 0.0), spawn(2, 10.0, 0.0, 0.0)];
+        let nearest = get_nearest_neighbours(&spawns);
+        assert_eq!(nearest[0].neighbour, Some(1));
+        assert_eq!(nearest[0].distance, Some(5.0));
+        assert_eq!(nearest[2].neighbour, Some(1));
+        assert_eq!(get_nearest_neighbours(&spawns[..1])[0].neighbour, None);
+    }
+
+    #[test]
+    fn counts_are_grouped_by_key() {
+        let spawns = [spawn(0, 0.0, 0.0, 0.5), spawn(1, 0.0, 0.0, 0.9), spawn(2, 0.0, 0.0, -0.5)];
+        let counts = count_by(&spawns, |spawn| (spawn.position.z / HEIGHT_BAND).floor() as i32);
+        assert_eq!(counts.into_iter().collect::<Vec<_>>(), vec![(-1, 1), (0, 2)]);
+    }
+}
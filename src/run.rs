@@ -0,0 +1,461 @@
+use std::path::Path;
+use ringhopper::definitions::{Bitmap, Scenario, ScenarioStructureBSP};
+use ringhopper::primitives::primitive::{TagGroup, TagPath, TagReference};
+use crate::backup::Backup;
+use crate::clean::find_unreferenced_randoms;
+use crate::error::Error;
+use crate::ground::{snap_to_ground, GroundSnapping};
+use crate::layers::TagsLayers;
+use crate::lm_render::LmRenderer;
+use crate::manifest::{delete_manifest, read_manifest, write_manifest, Manifest};
+use crate::markers::{add_spawn_markers, copy_recorded_markers, prepare_marker_tags, remove_markers, remove_markers_at_spawns, remove_recorded_markers, MarkerSet};
+use crate::migrate::{find_spawn_tools_artifacts, print_spawn_tools_artifacts, remove_spawn_tools_artifacts};
+use crate::output::{progress, warning, Event, EventSink};
+use crate::plan::{ChangePlan, ScenarioSnapshot};
+use crate::report::SpawnReport;
+use crate::randoms::{check_lm_bitmap, generate_randoms, get_original_lm_tag_path, get_output_lm_tag_path, get_tool_lm_tag_path, is_output_lm_tag_path, malformed_bsp_err, point_bsp_at_randoms, RenderOptions};
+use crate::spawns::{get_marker_spawns, get_slayer_spawns, get_spawns};
+use crate::tags::{delete_tag, get_bsp_tag_path, open_tag, open_tags_directory, parse_tag_path, tag_group_err, write_tag, LayeredTags};
+
+/// Options for placing spawn markers.
+pub struct MarkerOptions {
+    /// The marker scenery placed at spawns.
+    pub markers: MarkerSet,
+    /// If set, markers are moved onto the BSP's collision geometry.
+    pub ground_snapping: Option<GroundSnapping>,
+}
+
+/// What `apply` does to a scenario.
+pub struct SpawnOptions {
+    /// None to only place markers, leaving any previously rendered randoms as-is.
+    pub render: Option<RenderOptions>,
+    /// None to only render randoms, leaving any previously placed markers as-is.
+    pub markers: Option<MarkerOptions>,
+}
+
+/// What `migrate` looks for, and whether it removes what it finds.
+pub struct MigrateOptions {
+    /// Matched anywhere in scenery and BSP shader tag paths, ignoring case, in addition to the marker scenery
+    /// h1-spawn-tools ships with.
+    pub patterns: Vec<String>,
+    /// If false, artifacts are only listed.
+    pub remove: bool,
+}
+
+/// Renders randoms into the BSP's lightmap and places spawn markers in the scenario, as set by the options. Randoms or
+/// markers from a previous run which the options don't include are kept. With an output directory, the scenario and BSP
+/// written there are rebuilt from the sources every run. The renderer is only needed when the options include
+/// rendering. Returns a summary of what was done.
+pub fn apply(tags: &mut LayeredTags, renderer: Option<&LmRenderer>, scenario_tag_path: &TagPath, manifest_path: &Path, options: &SpawnOptions, plan: &mut ChangePlan, sink: &dyn EventSink) -> Result<String, Error> {
+    let mut scenario_tag = open_tag(tags.sources(), scenario_tag_path, sink)?;
+    let scenario = scenario_tag.get_mut::<Scenario>().ok_or_else(|| tag_group_err(scenario_tag_path))?;
+    let scenario_before = ScenarioSnapshot::new(scenario);
+
+    let mut previous_manifest = read_manifest(manifest_path)?;
+    let mut manifest = Manifest::default();
+
+    //marker tags are checked, or generated, before the BSP is written so a missing marker can't fail the run after the
+    //BSP already references the randoms
+    if let Some(marker_options) = &options.markers {
+        prepare_marker_tags(tags, &get_marker_spawns(scenario, &marker_options.markers), &marker_options.markers, plan, sink)?;
+    }
+
+    match renderer.zip(options.render.as_ref()) {
+        Some((renderer, render_options)) => {
+            let slayer_spawns = get_slayer_spawns(scenario);
+            sink.emit(Event::Spawns { total: scenario.player_starting_locations.items.len(), slayer: slayer_spawns.len() });
+            let bsp_tag_path = get_bsp_tag_path(scenario_tag_path, scenario)?;
+            let previous_lightmap = previous_manifest.as_ref().and_then(|previous_manifest| previous_manifest.lightmap.as_ref());
+            manifest.lightmap = Some(generate_randoms(tags, renderer, &slayer_spawns, bsp_tag_path, previous_lightmap, render_options, plan, sink)?);
+            //the original lightmap is recorded as soon as the BSP references the randoms, so reset can restore it even
+            //if a later step fails. until the scenario is written, its markers are still the previous run's
+            let randoms_manifest = Manifest { lightmap: manifest.lightmap.clone(), ..previous_manifest.clone().unwrap_or_default() };
+            write_manifest(manifest_path, &randoms_manifest, plan, sink)?;
+        },
+        //randoms rendered by an earlier run are kept so reset can still restore the original lightmap
+        None => {
+            manifest.lightmap = previous_manifest.as_mut().and_then(|previous_manifest| previous_manifest.lightmap.take());
+            if let Some(lightmap) = manifest.lightmap.as_ref().filter(|_| tags.is_overlay()) {
+                point_bsp_at_randoms(tags, lightmap, plan, sink)?;
+            }
+        },
+    }
+
+    let Some(marker_options) = &options.markers else {
+        //likewise markers placed by an earlier run are kept. an output directory's scenario is rebuilt with them
+        match previous_manifest {
+            Some(previous_manifest) if tags.is_overlay() && !previous_manifest.markers.is_empty() => {
+                let previous_scenario_tag = open_tag(&tags.all, scenario_tag_path, sink)?;
+                let previous_scenario = previous_scenario_tag.get_ref::<Scenario>().ok_or_else(|| tag_group_err(scenario_tag_path))?;
+                copy_recorded_markers(previous_scenario, scenario, &previous_manifest, &mut manifest, sink)?;
+                plan.record_scenario(&scenario_before, scenario);
+                write_tag(tags, scenario_tag_path, scenario, plan, sink)?;
+            },
+            Some(previous_manifest) if !tags.is_overlay() => {
+                manifest.scenery_palette = previous_manifest.scenery_palette;
+                manifest.markers = previous_manifest.markers;
+            },
+            _ => (),
+        }
+        write_manifest(manifest_path, &manifest, plan, sink)?;
+        return Ok("Randoms rendered successfully".into());
+    };
+
+    //markers from a previous run are replaced, keeping any palette entries we added which are still in use. the
+    //sources never have them with an output directory
+    match &previous_manifest {
+        Some(_) if tags.is_overlay() => (),
+        Some(previous_manifest) => manifest.scenery_palette = remove_recorded_markers(scenario, previous_manifest, sink),
+        None => {
+            let kept = remove_markers_at_spawns(scenario, &get_marker_spawns(scenario, &marker_options.markers), &marker_options.markers, sink);
+            if kept > 0 {
+                warning!(sink, "Kept {} placements of the marker scenery which aren't at a spawn and weren't recorded in a manifest, in case they were placed by hand", kept);
+            }
+        }
+    }
+
+    //markers may be moved onto the ground, but the spawns themselves are left as-is
+    let marker_spawns = get_marker_spawns(scenario, &marker_options.markers);
+    let marker_spawns = match &marker_options.ground_snapping {
+        Some(snapping) => {
+            let bsp_tag_path = get_bsp_tag_path(scenario_tag_path, scenario)?;
+            let bsp_tag = open_tag(tags.sources(), bsp_tag_path, sink)?;
+            let bsp = bsp_tag.get_ref::<ScenarioStructureBSP>().ok_or_else(|| tag_group_err(bsp_tag_path))?;
+            let collision_bsp = bsp.collision_bsp.items.first().ok_or_else(|| malformed_bsp_err(bsp_tag_path, None, None, None, "it has no collision geometry to snap markers to".into()))?;
+            progress!(sink, "Snapping markers to the ground");
+            snap_to_ground(&marker_spawns, collision_bsp, snapping, sink)
+        },
+        None => marker_spawns,
+    };
+    add_spawn_markers(scenario, &marker_spawns, &marker_options.markers, &mut manifest, sink)?;
+    plan.record_scenario(&scenario_before, scenario);
+    write_tag(tags, scenario_tag_path, scenario, plan, sink)?;
+    write_manifest(manifest_path, &manifest, plan, sink)?;
+
+    match options.render {
+        Some(_) => Ok("Spawns added successfully".into()),
+        None => Ok("Markers placed successfully".into()),
+    }
+}
+
+/// Removes the markers and lightmap reference recorded in the scenario's manifest, or for scenarios without one, every
+/// placement of the marker set's scenery and any reference to the BSP's randoms bitmap. With an output directory, its
+/// copies of the scenario and BSP are deleted instead. The randoms bitmap is deleted unless `keep_bitmap` is set.
+/// Returns a summary of what was done.
+pub fn reset(tags: &mut LayeredTags, scenario_tag_path: &TagPath, manifest_path: &Path, markers: &MarkerSet, keep_bitmap: bool, plan: &mut ChangePlan, sink: &dyn EventSink) -> Result<String, Error> {
+    let manifest = read_manifest(manifest_path)?;
+    if let Some(manifest) = manifest.as_ref().filter(|_| tags.is_overlay()) {
+        return reset_overlay(scenario_tag_path, manifest_path, manifest, keep_bitmap, plan, sink);
+    }
+
+    //without a manifest an output directory's copies can't be told from ones the user put there, so they're reset
+    //like any other
+    let mut scenario_tag = open_tag(&tags.all, scenario_tag_path, sink)?;
+    let scenario = scenario_tag.get_mut::<Scenario>().ok_or_else(|| tag_group_err(scenario_tag_path))?;
+    let scenario_before = ScenarioSnapshot::new(scenario);
+
+    let manifest = match manifest {
+        Some(manifest) => manifest,
+        None => {
+            progress!(sink, "No manifest found at {}, so markers will be identified by their scenery palette entries", manifest_path.display());
+            let message = reset_legacy(tags, scenario_tag_path, scenario, markers, keep_bitmap, plan, sink)?;
+            plan.record_scenario(&scenario_before, scenario);
+            return Ok(message);
+        }
+    };
+
+    if let Some(lightmap) = &manifest.lightmap {
+        let bsp_tag_path = parse_tag_path(&lightmap.bsp, TagGroup::ScenarioStructureBSP)?;
+        let original_lm_tag_path = parse_tag_path(&lightmap.original, TagGroup::Bitmap)?;
+        progress!(sink, "Resetting BSP lightmap reference to {}", original_lm_tag_path);
+        let mut bsp_tag = open_tag(&tags.all, &bsp_tag_path, sink)?;
+        let bsp = bsp_tag.get_mut::<ScenarioStructureBSP>().ok_or_else(|| tag_group_err(&bsp_tag_path))?;
+        bsp.lightmaps_bitmap = TagReference::Set(original_lm_tag_path);
+        write_tag(tags, &bsp_tag_path, bsp, plan, sink)?;
+        if !keep_bitmap {
+            delete_tag(&parse_tag_path(&lightmap.randoms, TagGroup::Bitmap)?, plan, sink)?;
+        }
+    }
+
+    remove_recorded_markers(scenario, &manifest, sink);
+    plan.record_scenario(&scenario_before, scenario);
+    write_tag(tags, scenario_tag_path, scenario, plan, sink)?;
+    delete_manifest(manifest_path, plan, sink)?;
+
+    Ok("Scenario reset successfully".into())
+}
+
+//the output directory's scenario and BSP are only ever spawn-camp's copies, so removing them leaves the sources
+fn reset_overlay(scenario_tag_path: &TagPath, manifest_path: &Path, manifest: &Manifest, keep_bitmap: bool, plan: &mut ChangePlan, sink: &dyn EventSink) -> Result<String, Error> {
+    progress!(sink, "Deleting the output directory's copies so the source tags are used again");
+    delete_tag(scenario_tag_path, plan, sink)?;
+    if let Some(lightmap) = &manifest.lightmap {
+        delete_tag(&parse_tag_path(&lightmap.bsp, TagGroup::ScenarioStructureBSP)?, plan, sink)?;
+        if !keep_bitmap {
+            delete_tag(&parse_tag_path(&lightmap.randoms, TagGroup::Bitmap)?, plan, sink)?;
+        }
+    }
+    delete_manifest(manifest_path, plan, sink)?;
+
+    Ok("Scenario reset successfully".into())
+}
+
+//resets scenarios which were given spawns before manifests were recorded
+fn reset_legacy(tags: &mut LayeredTags, scenario_tag_path: &TagPath, scenario: &mut Scenario, markers: &MarkerSet, keep_bitmap: bool, plan: &mut ChangePlan, sink: &dyn EventSink) -> Result<String, Error> {
+    if let Some(bsp_tag_path) = scenario.structure_bsps.items.first().and_then(|scnr_bsp| scnr_bsp.structure_bsp.path()) {
+        let mut bsp_tag = open_tag(&tags.all, bsp_tag_path, sink)?;
+        let bsp = bsp_tag.get_mut::<ScenarioStructureBSP>().ok_or_else(|| tag_group_err(bsp_tag_path))?;
+        //only a reference to our randoms bitmap is replaced, since anything else wasn't set by spawn-camp
+        if bsp.lightmaps_bitmap.path().is_some_and(|lm_tag_path| is_output_lm_tag_path(lm_tag_path, bsp_tag_path)) {
+            let original_lm_tag_path = get_tool_lm_tag_path(bsp_tag_path);
+            progress!(sink, "Resetting BSP lightmap reference to {}", original_lm_tag_path);
+            bsp.lightmaps_bitmap = TagReference::Set(original_lm_tag_path);
+            write_tag(tags, bsp_tag_path, bsp, plan, sink)?;
+        } else {
+            progress!(sink, "BSP doesn't reference a randoms lightmap, leaving its lightmap reference as-is");
+        }
+        if !keep_bitmap {
+            delete_tag(&get_output_lm_tag_path(bsp_tag_path), plan, sink)?;
+        }
+    }
+
+    if remove_markers(scenario, markers, sink) {
+        write_tag(tags, scenario_tag_path, scenario, plan, sink)?;
+    }
+
+    Ok("Scenario reset successfully".into())
+}
+
+/// Lists the marker and randoms scenery injected by h1-spawn-tools which spawn-camp didn't place itself, along with
+/// randoms geometry compiled into the BSP. Markers are only counted as injected if they're at a spawn and not recorded in
+/// the manifest. With `remove` set, the injected scenery placements and palette entries are removed from the scenario.
+/// BSP geometry is only reported, since it can't be separated from the level's own once compiled. Removing changes the
+/// source scenario, so it can't be done with an output directory. Returns a summary of what was done.
+pub fn migrate(tags: &mut LayeredTags, scenario_tag_path: &TagPath, manifest_path: &Path, options: &MigrateOptions, plan: &mut ChangePlan, sink: &dyn EventSink) -> Result<String, Error> {
+    if options.remove && tags.is_overlay() {
+        return Err(Error::Options("Removing injected scenery changes the source scenario, which is left untouched when writing to an output directory. Run migrate --remove without --output-tags".into()));
+    }
+    let mut scenario_tag = open_tag(tags.sources(), scenario_tag_path, sink)?;
+    let scenario = scenario_tag.get_mut::<Scenario>().ok_or_else(|| tag_group_err(scenario_tag_path))?;
+    let scenario_before = ScenarioSnapshot::new(scenario);
+
+    let bsp_tag_path = get_bsp_tag_path(scenario_tag_path, scenario)?;
+    let bsp_tag = open_tag(tags.sources(), bsp_tag_path, sink)?;
+    let bsp = bsp_tag.get_ref::<ScenarioStructureBSP>().ok_or_else(|| tag_group_err(bsp_tag_path))?;
+    let manifest = read_manifest(manifest_path)?;
+
+    progress!(sink, "Looking for h1-spawn-tools markers and randoms");
+    let artifacts = find_spawn_tools_artifacts(scenario, bsp, &get_spawns(scenario), manifest.as_ref(), &options.patterns);
+    print_spawn_tools_artifacts(scenario, &artifacts, sink);
+    if artifacts.is_empty() {
+        return Ok("No h1-spawn-tools markers or randoms were found".into());
+    }
+    //tool merges the randoms into the level's surfaces, collision, clusters and lightmap UVs when compiling the BSP, and
+    //nothing in the tag says which of those came from them, so they can't be cut back out of the tag
+    if !artifacts.bsp_shaders.is_empty() {
+        warning!(sink, "Randoms geometry is compiled into the BSP and can't be removed by spawn-camp. Remove it from the level's source geometry and recompile the BSP.");
+    }
+
+    let scenery_count = artifacts.scenery_indices.len();
+    let palette_count = artifacts.palette_indices.len();
+    if !options.remove {
+        return Ok(format!("Found {} injected scenery placements and {} scenery palette entries, none were removed", scenery_count, palette_count));
+    }
+    if scenery_count == 0 && palette_count == 0 {
+        return Ok("No injected scenery to remove".into());
+    }
+    progress!(sink, "Removing {} injected scenery placements and {} scenery palette entries", scenery_count, palette_count);
+    remove_spawn_tools_artifacts(scenario, &artifacts, sink);
+    plan.record_scenario(&scenario_before, scenario);
+    write_tag(tags, scenario_tag_path, scenario, plan, sink)?;
+
+    Ok(format!("Removed {} injected scenery placements and {} scenery palette entries", scenery_count, palette_count))
+}
+
+/// Checks the original lightmap bitmap of the scenario's BSP for problems which would prevent rendering randoms.
+pub fn validate(tags: &LayeredTags, scenario_tag_path: &TagPath, manifest_path: &Path, sink: &dyn EventSink) -> Result<String, Error> {
+    let scenario_tag = open_tag(tags.sources(), scenario_tag_path, sink)?;
+    let scenario = scenario_tag.get_ref::<Scenario>().ok_or_else(|| tag_group_err(scenario_tag_path))?;
+
+    let bsp_tag_path = get_bsp_tag_path(scenario_tag_path, scenario)?;
+    let bsp_tag = open_tag(tags.sources(), bsp_tag_path, sink)?;
+    let bsp = bsp_tag.get_ref::<ScenarioStructureBSP>().ok_or_else(|| tag_group_err(bsp_tag_path))?;
+
+    let manifest = read_manifest(manifest_path)?;
+    let lm_tag_path = get_original_lm_tag_path(bsp, bsp_tag_path, manifest.as_ref().and_then(|manifest| manifest.lightmap.as_ref()), sink)?;
+    progress!(sink, "Validating lightmap bitmap {}", lm_tag_path);
+    let lm_tag = open_tag(tags.sources(), &lm_tag_path, sink)?;
+    let lm = lm_tag.get_ref::<Bitmap>().ok_or_else(|| tag_group_err(&lm_tag_path))?;
+    check_lm_bitmap(lm, bsp, &lm_tag_path)?;
+
+    Ok(format!("Lightmap bitmap is valid with {} page(s)", lm.bitmap_data.items.len()))
+}
+
+/// The tags a run on the scenario reads: the scenario itself and the original lightmap bitmap of its BSP, which is
+/// found the same way `apply` finds it. The lightmap is left out if the scenario has no BSP or the BSP has no lightmap.
+pub fn get_source_tags(tags: &LayeredTags, scenario_tag_path: &TagPath, manifest_path: &Path, sink: &dyn EventSink) -> Result<Vec<TagPath>, Error> {
+    let mut tag_paths: Vec<TagPath> = vec![scenario_tag_path.clone()];
+    let scenario_tag = open_tag(tags.sources(), scenario_tag_path, sink)?;
+    let scenario = scenario_tag.get_ref::<Scenario>().ok_or_else(|| tag_group_err(scenario_tag_path))?;
+    let Ok(bsp_tag_path) = get_bsp_tag_path(scenario_tag_path, scenario) else {
+        return Ok(tag_paths);
+    };
+    let bsp_tag = open_tag(tags.sources(), bsp_tag_path, sink)?;
+    let bsp = bsp_tag.get_ref::<ScenarioStructureBSP>().ok_or_else(|| tag_group_err(bsp_tag_path))?;
+
+    let manifest = read_manifest(manifest_path)?;
+    if let Ok(lm_tag_path) = get_original_lm_tag_path(bsp, bsp_tag_path, manifest.as_ref().and_then(|manifest| manifest.lightmap.as_ref()), sink) {
+        tag_paths.push(lm_tag_path);
+    }
+    Ok(tag_paths)
+}
+
+/// Gathers statistics about the scenario's spawn layout, using its BSP to find which cluster each spawn is in.
+pub fn report(tags: &LayeredTags, scenario_tag_path: &TagPath, sink: &dyn EventSink) -> Result<SpawnReport, Error> {
+    let scenario_tag = open_tag(tags.sources(), scenario_tag_path, sink)?;
+    let scenario = scenario_tag.get_ref::<Scenario>().ok_or_else(|| tag_group_err(scenario_tag_path))?;
+
+    let bsp_tag_path = get_bsp_tag_path(scenario_tag_path, scenario)?;
+    let bsp_tag = open_tag(tags.sources(), bsp_tag_path, sink)?;
+    let bsp = bsp_tag.get_ref::<ScenarioStructureBSP>().ok_or_else(|| tag_group_err(bsp_tag_path))?;
+    let collision_bsp = bsp.collision_bsp.items.first().ok_or_else(|| malformed_bsp_err(bsp_tag_path, None, None, None, "it has no collision geometry to find clusters with".into()))?;
+
+    Ok(SpawnReport::new(scenario_tag_path.path(), scenario, bsp, collision_bsp))
+}
+
+/// Deletes randoms bitmaps in the writable layer which no BSP in any layer references.
+pub fn clean(layers: TagsLayers, dry_run: bool, sink: &dyn EventSink) -> Result<String, Error> {
+    let tags = open_tags_directory(&layers)?;
+    progress!(sink, "Looking for unreferenced randoms lightmap bitmaps in {}", layers.write.display());
+    let unreferenced = find_unreferenced_randoms(&tags.all, &layers, sink)?;
+
+    let mut plan = ChangePlan::new(dry_run, layers);
+    if !dry_run {
+        plan.backup = Some(Backup::new(&plan.layers.write));
+    }
+    unreferenced.iter().try_for_each(|lm_tag_path| delete_tag(lm_tag_path, &mut plan, sink))?;
+
+    if dry_run {
+        Ok(format!("{} unreferenced randoms bitmap(s) found, none were deleted", unreferenced.len()))
+    } else {
+        Ok(format!("{} unreferenced randoms bitmap(s) deleted", unreferenced.len()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::output::Discard;
+    use std::cell::RefCell;
+    use std::{fs, process};
+    use std::path::PathBuf;
+    use ringhopper::definitions::{ScenarioPlayerStartingLocation, ScenarioSpawnType, Scenery};
+    use ringhopper::primitives::primitive::Vector3D;
+    use ringhopper::primitives::tag::PrimaryTagStructDyn;
+    use ringhopper::tag::tree::{TagTree, VirtualTagsDirectory};
+    use crate::manifest::get_manifest_path;
+
+    fn tag_path(path: &str, group: TagGroup) -> TagPath {
+        TagPath::new(path, group).unwrap()
+    }
+
+    fn write_base_tag(base: &Path, tag_path: &TagPath, tag: &dyn PrimaryTagStructDyn) {
+        VirtualTagsDirectory::new(&[base], Some(0)).unwrap().write_tag(tag_path, tag).unwrap();
+    }
+
+    //a scenario with a slayer spawn at each x
+    fn scenario(xs: &[f32]) -> Scenario {
+        let mut scenario = Scenario::default();
+        for x in xs {
+            scenario.player_starting_locations.items.push(ScenarioPlayerStartingLocation {
+                position: Vector3D { x: *x, y: 0.0, z: 0.0 },
+                type_0: ScenarioSpawnType::Slayer,
+                ..Default::default()
+            });
+        }
+        scenario
+    }
+
+    fn markers_only() -> SpawnOptions {
+        let markers = MarkerSet {
+            default: tag_path("scenery\\spawn_marker\\spawn_marker", TagGroup::Scenery),
+            types: Vec::new(),
+            teams: Vec::new(),
+            named: false,
+            create_missing: false,
+        };
+        SpawnOptions { render: None, markers: Some(MarkerOptions { markers, ground_snapping: None }) }
+    }
+
+    fn run_apply(layers: &TagsLayers, scenario_tag_path: &TagPath, sink: &dyn EventSink) {
+        let mut tags = open_tags_directory(layers).unwrap();
+        let manifest_path = get_manifest_path(&layers.write, scenario_tag_path);
+        let mut plan = ChangePlan::new(false, layers.clone());
+        apply(&mut tags, None, scenario_tag_path, &manifest_path, &markers_only(), &mut plan, sink).unwrap();
+    }
+
+    fn read_scenario(dir: &Path, scenario_tag_path: &TagPath) -> Scenario {
+        let tags = VirtualTagsDirectory::new(&[dir], None).unwrap();
+        open_tag(&tags, scenario_tag_path, &Discard).unwrap().get_ref::<Scenario>().unwrap().clone()
+    }
+
+    fn get_test_dirs(name: &str) -> (PathBuf, PathBuf) {
+        let dir = std::env::temp_dir().join(format!("spawn-camp-{}-{}", name, process::id()));
+        let (base, output) = (dir.join("tags"), dir.join("tags-randoms"));
+        fs::create_dir_all(&base).unwrap();
+        fs::create_dir_all(&output).unwrap();
+        (base, output)
+    }
+
+    #[test]
+    fn output_directory_runs_see_edits_to_the_source_scenario() {
+        let (base, output) = get_test_dirs("run-overlay");
+        let scenario_tag_path = tag_path("levels\\test\\overlay\\overlay", TagGroup::Scenario);
+        write_base_tag(&base, &tag_path("scenery\\spawn_marker\\spawn_marker", TagGroup::Scenery), &Scenery::default());
+        write_base_tag(&base, &scenario_tag_path, &scenario(&[1.0]));
+        let layers = TagsLayers::new_overlay(&output, &[&base]).unwrap();
+
+        run_apply(&layers, &scenario_tag_path, &Discard);
+        assert_eq!(read_scenario(&output, &scenario_tag_path).scenery.items.len(), 1);
+
+        //a spawn added to the source after the first run shows up in the second, with no stale markers left over
+        write_base_tag(&base, &scenario_tag_path, &scenario(&[1.0, 2.0]));
+        run_apply(&layers, &scenario_tag_path, &Discard);
+        let output_scenario = read_scenario(&output, &scenario_tag_path);
+        assert_eq!(output_scenario.player_starting_locations.items.len(), 2);
+        let marker_xs: Vec<f32> = output_scenario.scenery.items.iter().map(|scenery| scenery.placement.position.x).collect();
+        assert_eq!(marker_xs, vec![1.0, 2.0]);
+        assert_eq!(output_scenario.scenery_palette.items.len(), 1);
+
+        //the source is never written to
+        assert!(read_scenario(&base, &scenario_tag_path).scenery.items.is_empty());
+
+        //reset removes the output directory's copy so the source is used again
+        let mut tags = open_tags_directory(&layers).unwrap();
+        let manifest_path = get_manifest_path(&layers.write, &scenario_tag_path);
+        let markers = markers_only().markers.unwrap().markers;
+        reset(&mut tags, &scenario_tag_path, &manifest_path, &markers, false, &mut ChangePlan::new(false, layers.clone()), &Discard).unwrap();
+        assert!(!VirtualTagsDirectory::new(&[&output], None).unwrap().contains(&scenario_tag_path));
+        assert!(!manifest_path.exists());
+        fs::remove_dir_all(base.parent().unwrap()).unwrap();
+    }
+
+    #[test]
+    fn events_go_to_the_given_sink() {
+        let (base, output) = get_test_dirs("run-sink");
+        let scenario_tag_path = tag_path("levels\\test\\sink\\sink", TagGroup::Scenario);
+        write_base_tag(&base, &tag_path("scenery\\spawn_marker\\spawn_marker", TagGroup::Scenery), &Scenery::default());
+        write_base_tag(&base, &scenario_tag_path, &scenario(&[1.0, 2.0]));
+        let layers = TagsLayers::new_overlay(&output, &[&base]).unwrap();
+
+        let events: RefCell<Vec<String>> = RefCell::new(Vec::new());
+        let collect = |event: Event| events.borrow_mut().push(serde_json::to_value(&event).unwrap()["event"].as_str().unwrap().to_owned());
+        run_apply(&layers, &scenario_tag_path, &collect);
+        let events = events.into_inner();
+        assert!(events.contains(&"tag_read".to_owned()));
+        assert!(events.contains(&"markers_placed".to_owned()));
+        assert_eq!(events.iter().filter(|event| *event == "tag_written").count(), 1);
+        assert_eq!(events.last().unwrap(), "manifest_written");
+        fs::remove_dir_all(base.parent().unwrap()).unwrap();
+    }
+}
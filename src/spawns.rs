@@ -0,0 +1,50 @@
+use ringhopper::definitions::{Scenario, ScenarioSpawnType};
+use ringhopper::primitives::primitive::{Angle, Vector3D};
+use crate::markers::MarkerSet;
+
+/// A player starting location read from a scenario.
+#[derive(Clone)]
+pub struct SpawnInfo {
+    /// Index in the scenario's player starting locations.
+    pub index: usize,
+    /// World position.
+    pub position: Vector3D,
+    /// Facing around the vertical axis.
+    pub facing: Angle,
+    /// Team index, where 0 is red and 1 is blue.
+    pub team_index: u16,
+    /// The four spawn type slots, where unused slots are `ScenarioSpawnType::None`.
+    pub types: [ScenarioSpawnType; 4],
+}
+
+/// Every player starting location in the scenario, in order.
+pub fn get_spawns(scenario: &Scenario) -> Vec<SpawnInfo> {
+    scenario.player_starting_locations.items.iter().enumerate().map(|(index, loc)| {
+        SpawnInfo {
+            index,
+            position: loc.position,
+            facing: loc.facing,
+            team_index: loc.team_index,
+            types: [loc.type_0, loc.type_1, loc.type_2, loc.type_3],
+        }
+    }).collect()
+}
+
+/// Spawns used in slayer, which are the ones randoms are rendered for.
+pub fn get_slayer_spawns(scenario: &Scenario) -> Vec<SpawnInfo> {
+    get_spawns(scenario).into_iter()
+        .filter(|spawn| spawn.types.iter().any(|spawn_type| is_slayer_spawn(*spawn_type)))
+        .collect()
+}
+
+/// Spawns which get a marker: slayer spawns, plus spawns of any type the marker set has its own marker for.
+pub fn get_marker_spawns(scenario: &Scenario, markers: &MarkerSet) -> Vec<SpawnInfo> {
+    get_spawns(scenario).into_iter()
+        .filter(|spawn| spawn.types.iter().any(|spawn_type| is_slayer_spawn(*spawn_type)) || markers.has_type_marker(spawn))
+        .collect()
+}
+
+/// Whether a spawn of this type is used in slayer games.
+pub fn is_slayer_spawn(spawn_type: ScenarioSpawnType) -> bool {
+    matches!(spawn_type, ScenarioSpawnType::Slayer | ScenarioSpawnType::AllGames | ScenarioSpawnType::AllExceptCtf | ScenarioSpawnType::AllExceptRaceAndCtf)
+}
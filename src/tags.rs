@@ -0,0 +1,124 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use ringhopper::definitions::Scenario;
+use ringhopper::error::Error as RinghopperError;
+use ringhopper::primitives::primitive::{TagGroup, TagPath};
+use ringhopper::primitives::tag::PrimaryTagStructDyn;
+use ringhopper::tag::tree::{TagTree, VirtualTagsDirectory};
+use crate::error::Error;
+use crate::layers::TagsLayers;
+use crate::output::{Event, EventSink};
+use crate::plan::ChangePlan;
+
+/// The tags directories a run works with.
+pub struct LayeredTags {
+    /// Every layer, which tags are written to and generated tags like marker scenery are read from.
+    pub all: VirtualTagsDirectory,
+    //the base layers, only opened separately when there's an output directory
+    sources: Option<VirtualTagsDirectory>,
+}
+
+impl LayeredTags {
+    /// Where the source scenario, BSP and lightmap are read from. With an output directory these are the base layers,
+    /// so a copy written there by an earlier run never hides edits to the original.
+    pub fn sources(&self) -> &VirtualTagsDirectory {
+        self.sources.as_ref().unwrap_or(&self.all)
+    }
+
+    /// Whether tags are written to an output directory over the sources.
+    pub fn is_overlay(&self) -> bool {
+        self.sources.is_some()
+    }
+}
+
+/// Opens the layers' tags directories, highest priority first. Tags are written to the write layer.
+pub fn open_tags_directory(layers: &TagsLayers) -> Result<LayeredTags, Error> {
+    let open = |tags_dirs: &[&Path], write: Option<usize>| VirtualTagsDirectory::new(tags_dirs, write)
+        .map_err(|err| Error::Io { message: format!("Could not read the tags directories: {}", display_ringhopper_err(err)), source: None });
+    Ok(LayeredTags {
+        all: open(&layers.all(), Some(0))?,
+        sources: if layers.overlay { Some(open(&layers.sources(), None)?) } else { None },
+    })
+}
+
+/// Reads a copy of a tag. Every tag read goes through here so it's reported with `--output json`.
+pub fn open_tag(tags: &VirtualTagsDirectory, tag_path: &TagPath, sink: &dyn EventSink) -> Result<Box<dyn PrimaryTagStructDyn>, Error> {
+    sink.emit(Event::TagRead { tag: tag_path.to_string() });
+    tags.open_tag_copy(tag_path).map_err(|err| open_tag_err(tag_path, err))
+}
+
+/// Writes a tag to the plan's writable layer, backing up the previous file first. With a dry run the write is only
+/// recorded in the plan.
+pub(crate) fn write_tag(tags: &mut LayeredTags, tag_path: &TagPath, tag: &dyn PrimaryTagStructDyn, plan: &mut ChangePlan, sink: &dyn EventSink) -> Result<(), Error> {
+    plan.tags.push(tag_path.to_string());
+    let dir = plan.layers.write.display().to_string();
+    if plan.dry_run {
+        sink.emit(Event::TagWritten { tag: tag_path.to_string(), dir, overrides: None, dry_run: true });
+        return Ok(());
+    }
+    if let Some(backup) = &mut plan.backup {
+        backup.save_tag(tag_path, sink)?;
+    }
+    let overrides = plan.layers.find_base_layer(tag_path).map(|base_dir| base_dir.display().to_string());
+    sink.emit(Event::TagWritten { tag: tag_path.to_string(), dir, overrides, dry_run: false });
+    tags.all.write_tag(tag_path, tag).map_err(|err| Error::TagIo { tag: tag_path.to_string(), message: format!("Could not write tag {}: {}", tag_path, display_ringhopper_err(err)) })?;
+    Ok(())
+}
+
+/// Removes a tag file spawn-camp generated from the plan's writable layer. Tags which don't exist are skipped.
+pub(crate) fn delete_tag(tag_path: &TagPath, plan: &mut ChangePlan, sink: &dyn EventSink) -> Result<(), Error> {
+    let file_path = plan.layers.write.join(get_tag_file_path(tag_path));
+    if !file_path.exists() {
+        return Ok(());
+    }
+    plan.deleted_tags.push(tag_path.to_string());
+    let dir = plan.layers.write.display().to_string();
+    if plan.dry_run {
+        sink.emit(Event::TagDeleted { tag: tag_path.to_string(), dir, dry_run: true });
+        return Ok(());
+    }
+    if let Some(backup) = &mut plan.backup {
+        backup.save_tag(tag_path, sink)?;
+    }
+    sink.emit(Event::TagDeleted { tag: tag_path.to_string(), dir, dry_run: false });
+    fs::remove_file(&file_path).map_err(|err| Error::TagIo { tag: tag_path.to_string(), message: format!("Could not delete tag {}: {}", tag_path, err) })
+}
+
+/// The tag's file relative to a tags directory: `levels\test\chillout\chillout.scenario` is stored at
+/// `levels/test/chillout/chillout.scenario`.
+pub fn get_tag_file_path(tag_path: &TagPath) -> PathBuf {
+    tag_path.to_string().split('\\').collect::<PathBuf>()
+}
+
+/// Parses a tag path without its extension, like `levels\test\chillout\chillout`, as a tag of the group.
+pub fn parse_tag_path(raw: &str, group: TagGroup) -> Result<TagPath, Error> {
+    TagPath::new(raw, group).map_err(|err| Error::Options(format!("Not a valid tag path: {}: {}", raw, display_ringhopper_err(err))))
+}
+
+/// The scenario's first BSP, which is the one spawn-camp works with.
+pub(crate) fn get_bsp_tag_path<'a>(scenario_tag_path: &TagPath, scenario: &'a Scenario) -> Result<&'a TagPath, Error> {
+    let missing_err = |message: &str| Error::MissingTag { tag: scenario_tag_path.to_string(), message: format!("The scenario {} {}", scenario_tag_path, message) };
+    scenario.structure_bsps.items.first().ok_or_else(|| missing_err("has no BSP"))
+        ?.structure_bsp.path().ok_or_else(|| missing_err("has an empty BSP tag path"))
+}
+
+fn open_tag_err(tag_path: &TagPath, err: RinghopperError) -> Error {
+    let tag = tag_path.to_string();
+    match err {
+        RinghopperError::TagNotFound(_) => Error::MissingTag { message: format!("The tag {} does not exist", tag_path), tag },
+        err => Error::TagIo { message: format!("Could not open tag {}: {}", tag_path, display_ringhopper_err(err)), tag },
+    }
+}
+
+/// The error for a tag which isn't of its tag path's group. Tag paths include their group, so this only happens if a
+/// tag file doesn't contain what its extension says.
+pub fn tag_group_err(tag_path: &TagPath) -> Error {
+    Error::TagIo { tag: tag_path.to_string(), message: format!("The tag {} could not be read as a {} tag", tag_path, tag_path.group().as_str()) }
+}
+
+pub(crate) fn display_ringhopper_err(err: RinghopperError) -> String {
+    match err {
+        RinghopperError::InvalidTagsDirectory => "Invalid tags directory".into(),
+        _ => format!("Unexpected error: {}", err.as_str()),
+    }
+}
@@ -0,0 +1,136 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::{Duration, Instant, SystemTime};
+use ringhopper::primitives::primitive::TagPath;
+use spawn_camp::tags::{get_tag_file_path, LayeredTags};
+use spawn_camp::layers::TagsLayers;
+use spawn_camp::manifest::get_manifest_path;
+use spawn_camp::output::{progress, Printer};
+use spawn_camp::run::get_source_tags;
+use spawn_camp::tags::open_tags_directory;
+use spawn_camp::error::Error;
+use crate::batch::{emit_scenario_finished, print_summary, run_scenarios, RunOptions, RunState, ScenarioResult};
+
+const POLL_INTERVAL: Duration = Duration::from_millis(500);
+//Sapien and tool can write a tag more than once per save, so a change is only acted on once files settle
+const DEBOUNCE: Duration = Duration::from_millis(1500);
+
+//a scenario's tag and original lightmap files, with their modification times as of before the last run
+pub struct WatchedScenario {
+    pub scenario_tag_path: TagPath,
+    files: Vec<PathBuf>,
+    modified: Vec<Option<SystemTime>>,
+}
+
+impl WatchedScenario {
+    //the lightmap is found through the BSP, so it's watched even before the first successful run. if the scenario
+    //can't be read, only its own files are watched until it's fixed
+    pub fn new(tags: &LayeredTags, layers: &TagsLayers, scenario_tag_path: &TagPath, printer: &Printer) -> WatchedScenario {
+        let manifest_path = get_manifest_path(&layers.write, scenario_tag_path);
+        let tag_paths = get_source_tags(tags, scenario_tag_path, &manifest_path, printer).unwrap_or_else(|_| vec![scenario_tag_path.clone()]);
+
+        //copies in every source layer are watched, since a higher layer's copy may be edited as well as the lower's.
+        //copies which don't exist yet are watched for being created. an output directory only has generated copies
+        let files: Vec<PathBuf> = tag_paths.iter()
+            .flat_map(|tag_path| {
+                let tag_file_path = get_tag_file_path(tag_path);
+                layers.sources().into_iter().map(move |tags_dir| tags_dir.join(&tag_file_path))
+            })
+            .collect();
+        let modified = get_modified_times(&files);
+
+        WatchedScenario {
+            scenario_tag_path: scenario_tag_path.clone(),
+            files,
+            modified,
+        }
+    }
+
+    //spawn-camp's own writes become the baseline rather than a change
+    pub fn accept_writes(&mut self, written_files: &[PathBuf]) {
+        self.files.iter().zip(self.modified.iter_mut())
+            .filter(|(file, _)| written_files.contains(file))
+            .for_each(|(file, modified)| *modified = get_modified_time(file));
+    }
+
+    fn has_changed(&self) -> bool {
+        get_modified_times(&self.files) != self.modified
+    }
+}
+
+//re-runs scenarios whenever they're saved, until the process is stopped
+pub fn run_watch(tags: &mut LayeredTags, layers: &TagsLayers, mut watched: Vec<WatchedScenario>, options: &RunOptions, state: &mut RunState, printer: &Printer) -> Result<String, Error> {
+    loop {
+        progress!(printer);
+        progress!(printer, "Watching {} scenario(s) for changes, press Ctrl+C to stop", watched.len());
+
+        let changed = wait_for_changes(&watched, printer);
+        if changed.is_empty() {
+            continue;
+        }
+        //tags are re-read from disk rather than a cached copy from the last run
+        *tags = open_tags_directory(layers)?;
+        let mut snapshots = take_snapshots(tags, layers, &changed, printer);
+        let results = run_scenarios(tags, layers, &changed, options, state, printer);
+        accept_writes(&mut snapshots, &results);
+        for snapshot in snapshots {
+            if let Some(previous) = watched.iter_mut().find(|previous| previous.scenario_tag_path == snapshot.scenario_tag_path) {
+                *previous = snapshot;
+            }
+        }
+        match &results[..] {
+            //json output already had a scenario_finished event
+            [_] if printer.json => (),
+            [result] if !options.dry_run || result.result.is_err() => emit_scenario_finished(&result.scenario, &result.result, result.duration, printer),
+            [_] => (),
+            results => print_summary(results, printer),
+        }
+    }
+}
+
+//taken before each run, so saves made while it runs are picked up afterwards
+pub fn take_snapshots(tags: &LayeredTags, layers: &TagsLayers, scenario_tag_paths: &[TagPath], printer: &Printer) -> Vec<WatchedScenario> {
+    scenario_tag_paths.iter().map(|scenario_tag_path| WatchedScenario::new(tags, layers, scenario_tag_path, printer)).collect()
+}
+
+//results are in the same order as the snapshots, since both follow the scenarios run
+pub fn accept_writes(snapshots: &mut [WatchedScenario], results: &[ScenarioResult]) {
+    snapshots.iter_mut().zip(results).for_each(|(snapshot, result)| snapshot.accept_writes(&result.written_files));
+}
+
+//blocks until at least one scenario changes and its files stop changing, then returns the changed scenarios
+fn wait_for_changes(watched: &[WatchedScenario], printer: &Printer) -> Vec<TagPath> {
+    let all_files: Vec<PathBuf> = watched.iter().flat_map(|scenario| scenario.files.iter().cloned()).collect();
+    loop {
+        thread::sleep(POLL_INTERVAL);
+        if !watched.iter().any(WatchedScenario::has_changed) {
+            continue;
+        }
+
+        progress!(printer, "Change detected, waiting for saving to finish");
+        let mut last_modified = get_modified_times(&all_files);
+        let mut last_change = Instant::now();
+        while last_change.elapsed() < DEBOUNCE {
+            thread::sleep(POLL_INTERVAL);
+            let modified = get_modified_times(&all_files);
+            if modified != last_modified {
+                last_modified = modified;
+                last_change = Instant::now();
+            }
+        }
+
+        return watched.iter()
+            .filter(|scenario| scenario.has_changed())
+            .map(|scenario| scenario.scenario_tag_path.clone())
+            .collect();
+    }
+}
+
+fn get_modified_times(files: &[PathBuf]) -> Vec<Option<SystemTime>> {
+    files.iter().map(|file| get_modified_time(file)).collect()
+}
+
+fn get_modified_time(file: &Path) -> Option<SystemTime> {
+    fs::metadata(file).and_then(|metadata| metadata.modified()).ok()
+}